@@ -1,17 +1,26 @@
-use rosella::{Lexer, Parser, Compiler, Shell, OS};
+// Diagnostics are only built on the error path, so their size is not a concern
+#![allow(clippy::result_large_err)]
+
+use rosella::{Lexer, Parser, Compiler, Shell, OS, Diagnostic, Severity, Renderer, codes, explain};
 
 use clap::{Parser as ClapParser, Subcommand, ValueEnum};
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 #[derive(ClapParser, Debug)]
 #[command(
     version = "0.1.0",
-    about = "A Command Line Interface for the Rosella programming language."
-
+    about = "A Command Line Interface for the Rosella programming language.",
+    after_help = "Exit codes:\n  0  success\n  1  syntax errors while lexing or parsing\n  2  invalid command line usage\n  3  compilation errors\n  4  unsupported target OS and shell combination\n  5  input or output file could not be read or written"
 )]
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Whether to colour diagnostics
+    #[arg(long, value_enum, global = true, default_value = "auto")]
+    colour: ColourChoice,
 }
 
 #[derive(Subcommand, Debug)]
@@ -28,6 +37,33 @@ enum Commands {
 
         #[arg(short, long, value_enum)]
         shell: Option<TargetShell>,
+
+        /// How diagnostics are printed
+        #[arg(long, value_enum, default_value = "human")]
+        message_format: MessageFormat,
+
+        /// Decimal places kept by float arithmetic
+        #[arg(long, value_name = "DIGITS", default_value_t = 2)]
+        float_precision: usize,
+    },
+    /// Check a file for errors without writing any output, stopping at the first error
+    Check {
+        #[arg(short, long, value_name = "FILE", value_parser = clap::value_parser!(PathBuf))]
+        input: PathBuf,
+
+        #[arg(short, long, value_enum)]
+        target: Option<TargetOS>,
+
+        #[arg(short, long, value_enum)]
+        shell: Option<TargetShell>,
+
+        /// How diagnostics are printed
+        #[arg(long, value_enum, default_value = "human")]
+        message_format: MessageFormat,
+    },
+    /// Print a detailed explanation of an error code, e.g. R0307
+    Explain {
+        code: String,
     }
 }
 
@@ -43,54 +79,184 @@ enum TargetShell {
     Bash,
 }
 
-fn main() {
-    let cli = Cli::parse();
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ColourChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColourChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColourChoice::Always => true,
+            ColourChoice::Never => false,
+            ColourChoice::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum MessageFormat {
+    Human,
+    Json,
+}
+
+// Process exit codes, so build pipelines can tell failures apart
+#[derive(Debug, Clone, Copy)]
+enum Failure {
+    Syntax = 1,
+    Usage = 2,
+    Compile = 3,
+    InvalidTarget = 4,
+    Io = 5,
+}
+
+impl From<Failure> for ExitCode {
+    fn from(failure: Failure) -> Self {
+        ExitCode::from(failure as u8)
+    }
+}
+
+// Prints diagnostics in the requested format and keeps count for the summary line
+struct Reporter<'a> {
+    renderer: Renderer<'a>,
+    format: MessageFormat,
+    errors: usize,
+    warnings: usize,
+}
+
+impl<'a> Reporter<'a> {
+    fn new(renderer: Renderer<'a>, format: MessageFormat) -> Self {
+        Reporter { renderer, format, errors: 0, warnings: 0 }
+    }
+
+    fn report(&mut self, diagnostic: &Diagnostic) {
+        match diagnostic.severity {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+            Severity::Note => {}
+        }
+
+        match self.format {
+            MessageFormat::Human => eprint!("{}", self.renderer.render(diagnostic)),
+            MessageFormat::Json => println!("{}", self.renderer.render_json(diagnostic)),
+        }
+    }
+
+    fn summary(&self) {
+        if self.format != MessageFormat::Human || self.errors + self.warnings == 0 {
+            return;
+        }
+
+        let mut parts = Vec::new();
+        if self.errors > 0 {
+            parts.push(plural(self.errors, "error"));
+        }
+        if self.warnings > 0 {
+            parts.push(plural(self.warnings, "warning"));
+        }
+        eprintln!("{}", parts.join(", "));
+
+        if self.errors > 0 {
+            eprintln!("For more information about an error, try `rosella explain <CODE>`.");
+        }
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    }
+    else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+fn read_input(input: &Path) -> Result<String, Diagnostic> {
+    std::fs::read_to_string(input).map_err(|e| {
+        Diagnostic::unlocated(codes::INPUT_NOT_READABLE, format!("Could not read input file {}: {}", input.display(), e))
+    })
+}
+
+// Reports a failure from before the input was read, when there is no source to point into
+fn report_unlocated(file_name: &str, colour: bool, format: MessageFormat, diagnostic: &Diagnostic) {
+    let mut reporter = Reporter::new(Renderer::new(file_name, "").colour(colour), format);
+    reporter.report(diagnostic);
+    reporter.summary();
+}
+
+// Falls back to the OS and shell of the machine running the compiler
+fn resolve_target(target: &Option<TargetOS>, shell: &Option<TargetShell>) -> Result<(OS, Shell), Diagnostic> {
     let current_os = std::env::consts::OS;
 
+    let target_os = match target {
+        Some(os) => {
+            match os {
+                TargetOS::Windows => OS::Windows,
+                TargetOS::Linux => OS::Linux,
+            }
+        }
+        None => {
+            if current_os == "windows" {
+                OS::Windows
+            } else {
+                OS::Linux
+            }
+        }
+    };
+
+    let target_shell = match shell {
+        Some(shell) => {
+            match shell {
+                TargetShell::Batch => Shell::Batch,
+                TargetShell::Bash => Shell::Bash,
+            }
+        }
+        None => {
+            if current_os == "windows" {
+                Shell::Batch
+            } else {
+                Shell::Bash
+            }
+        }
+    };
+
+    if target_os == OS::Linux && target_shell == Shell::Batch {
+        return Err(Diagnostic::unlocated(codes::UNSUPPORTED_TARGET, "Batch shell is not supported on Linux")
+            .with_help("target Windows with `--target windows`, or Linux with `--shell bash`"));
+    }
+
+    Ok((target_os, target_shell))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
     match &cli.command {
         Commands::Compile { 
             input, 
             output, 
             target, 
-            shell 
+            shell,
+            message_format,
+            float_precision,
         } => {
-            let input_content = match std::fs::read_to_string(input) {
+            let file_name = input.display().to_string();
+
+            let input_content = match read_input(input) {
                 Ok(content) => content,
                 Err(e) => {
-                    eprintln!("Error reading input file: {}", e);
-                    return;
-                }
-            };
-
-            let target_os = match target {
-                Some(os) => {
-                    match os {
-                        TargetOS::Windows => OS::Windows,
-                        TargetOS::Linux => OS::Linux,
-                    }
-                }
-                None => {
-                    if current_os == "windows" {
-                        OS::Windows
-                    } else {
-                        OS::Linux
-                    }
+                    report_unlocated(&file_name, cli.colour.enabled(), *message_format, &e);
+                    return Failure::Io.into();
                 }
             };
 
-            let target_shell = match shell {
-                Some(shell) => {
-                    match shell {
-                        TargetShell::Batch => Shell::Batch,
-                        TargetShell::Bash => Shell::Bash,
-                    }
-                }
-                None => {
-                    if current_os == "windows" {
-                        Shell::Batch
-                    } else {
-                        Shell::Bash
-                    }
+            let (target_os, target_shell) = match resolve_target(target, shell) {
+                Ok(target) => target,
+                Err(e) => {
+                    report_unlocated(&file_name, cli.colour.enabled(), *message_format, &e);
+                    return Failure::InvalidTarget.into();
                 }
             };
 
@@ -106,44 +272,121 @@ fn main() {
                 }
             };
 
-            if target_os == OS::Linux && target_shell == Shell::Batch {
-                eprintln!("Batch shell is not supported on Linux.");
-                return;
-            }
+            // Keep stdout clean for the JSON stream
+            let human = *message_format == MessageFormat::Human;
 
-            println!("Compiling {} for {:?} using {:?} shell", input.display(), target_os, target_shell);
+            if human {
+                println!("Compiling {} for {:?} using {:?} shell", input.display(), target_os, target_shell);
+            }
 
-            let mut lexer = Lexer::new(&input_content);
-            let tokens = match lexer.tokenise() {
-                Ok(tokens) => tokens,
-                Err(e) => {
-                    eprintln!("Error during tokenization: {}", e);
-                    return;
-                }
-            };
+            let renderer = Renderer::new(&file_name, &input_content).colour(cli.colour.enabled());
+            let mut reporter = Reporter::new(renderer, *message_format);
 
-            let mut parser = Parser::new(tokens);
+            // Parse straight from the lexer so its errors are reported together with the parser's
+            let mut parser = Parser::from_stream(Lexer::new(&input_content));
             let ast = match parser.parse() {
                 Ok(ast) => ast,
-                Err(e) => {
-                    eprintln!("Error during parsing: {}", e);
-                    return;
+                Err(errors) => {
+                    errors.iter().for_each(|e| reporter.report(e));
+                    reporter.summary();
+                    return Failure::Syntax.into();
                 }
             };
 
-            let output_content = match Compiler::new(ast, target_os, target_shell).compile() {
+            let output_content = match Compiler::new(ast, target_os, target_shell).float_precision(*float_precision).compile() {
                 Ok(output) => output,
                 Err(e) => {
-                    eprintln!("Error during compilation: {}", e);
-                    return;
+                    reporter.report(&e);
+                    reporter.summary();
+                    return Failure::Compile.into();
                 }
             };
 
             if let Err(e) = std::fs::write(&output, output_content) {
-                eprintln!("Error writing output file: {}", e);
-            } else {
+                reporter.report(&Diagnostic::unlocated(
+                    codes::OUTPUT_NOT_WRITABLE,
+                    format!("Could not write output file {}: {}", output.display(), e)));
+                reporter.summary();
+                return Failure::Io.into();
+            }
+
+            reporter.summary();
+
+            if human {
                 println!("Compilation successful! Output written to {}", output.display());
             }
+
+            ExitCode::SUCCESS
+        }
+        Commands::Check {
+            input,
+            target,
+            shell,
+            message_format,
+        } => {
+            let file_name = input.display().to_string();
+
+            let input_content = match read_input(input) {
+                Ok(content) => content,
+                Err(e) => {
+                    report_unlocated(&file_name, cli.colour.enabled(), *message_format, &e);
+                    return Failure::Io.into();
+                }
+            };
+
+            let (target_os, target_shell) = match resolve_target(target, shell) {
+                Ok(target) => target,
+                Err(e) => {
+                    report_unlocated(&file_name, cli.colour.enabled(), *message_format, &e);
+                    return Failure::InvalidTarget.into();
+                }
+            };
+
+            let human = *message_format == MessageFormat::Human;
+
+            if human {
+                println!("Checking {} for {:?} using {:?} shell", input.display(), target_os, target_shell);
+            }
+
+            let renderer = Renderer::new(&file_name, &input_content).colour(cli.colour.enabled());
+            let mut reporter = Reporter::new(renderer, *message_format);
+
+            // Parse straight from the lexer so the first error ends the check without lexing the rest
+            let mut parser = Parser::from_stream(Lexer::new(&input_content)).fail_fast(true);
+            let ast = match parser.parse() {
+                Ok(ast) => ast,
+                Err(errors) => {
+                    errors.iter().for_each(|e| reporter.report(e));
+                    reporter.summary();
+                    return Failure::Syntax.into();
+                }
+            };
+
+            if let Err(e) = Compiler::new(ast, target_os, target_shell).compile() {
+                reporter.report(&e);
+                reporter.summary();
+                return Failure::Compile.into();
+            }
+
+            reporter.summary();
+
+            if human {
+                println!("No errors found in {}", input.display());
+            }
+
+            ExitCode::SUCCESS
+        }
+        Commands::Explain { code } => {
+            match explain(code) {
+                Some(explanation) => {
+                    print!("{}", explanation);
+                    ExitCode::SUCCESS
+                }
+                None => {
+                    eprintln!("No explanation available for error code {}.", code);
+                    Failure::Usage.into()
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
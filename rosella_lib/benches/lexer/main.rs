@@ -0,0 +1,82 @@
+// Compares lexing throughput against the baseline `Vec<char>` lexer. Run with `cargo bench --bench lexer`
+mod legacy_lexer;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const TARGET_LINES: usize = 50_000;
+const RUNS: usize = 20;
+
+// One block of the kind of script our templates generate, with `{n}` replaced to keep names distinct.
+// It sticks to the syntax the baseline lexer understood, so both lexers see the same tokens
+const TEMPLATE: &str = r#"/* Step {n}: prepare the build directory */
+fn step_{n}(target, retries) {
+    let int attempt_{n} = 31 + 1000 * {n};
+    let float ratio_{n} = 0.75 * attempt_{n};
+    let str label_{n} = "step {n} of the build";
+    /* Retry until the build succeeds */
+    while int(attempt_{n} < retries) {
+        print("attempt for ", target)
+        let int attempt_{n} = attempt_{n} + 1;
+    }
+    if str(label_{n} != "done") {
+        make_dir(path("build/step_{n}"))
+        write_file(path("build/step_{n}/notes.txt"), concat("Generated for step {n}: ", label_{n}))
+    }
+    else {
+        |> echo "skipping step {n}";
+    }
+}
+"#;
+
+fn generate_source() -> String {
+    let lines_per_block = TEMPLATE.lines().count();
+    (0..TARGET_LINES / lines_per_block)
+        .map(|n| TEMPLATE.replace("{n}", &n.to_string()))
+        .collect()
+}
+
+// Times one lexing pass, returning the number of tokens produced
+fn time<F: FnMut() -> usize>(lex: &mut F) -> (Duration, usize) {
+    let start = Instant::now();
+    let tokens = black_box(lex());
+    (start.elapsed(), tokens)
+}
+
+fn report(name: &str, source: &str, elapsed: Duration, tokens: usize) {
+    let seconds = elapsed.as_secs_f64();
+    println!(
+        "{:<10} {:>10.2?} {:>10} tokens {:>8.1} MiB/s {:>12.0} lines/s",
+        name,
+        elapsed,
+        tokens,
+        source.len() as f64 / seconds / (1024.0 * 1024.0),
+        source.lines().count() as f64 / seconds,
+    );
+}
+
+fn main() {
+    let source = generate_source();
+    println!("Lexing {} lines ({} bytes), best of {} runs", source.lines().count(), source.len(), RUNS);
+
+    let mut lex_legacy = || legacy_lexer::Lexer::new(black_box(&source)).tokenise().expect("legacy lexer failed").len();
+    let mut lex_current = || rosella::Lexer::new(black_box(&source)).tokenise().expect("lexer failed").len();
+
+    // Alternate the lexers and keep the best run of each, so noise on the machine affects both alike
+    let (mut legacy, mut current) = (Duration::MAX, Duration::MAX);
+    let (mut legacy_tokens, mut current_tokens) = (0, 0);
+    for _ in 0..RUNS {
+        let (elapsed, tokens) = time(&mut lex_legacy);
+        legacy = legacy.min(elapsed);
+        legacy_tokens = tokens;
+
+        let (elapsed, tokens) = time(&mut lex_current);
+        current = current.min(elapsed);
+        current_tokens = tokens;
+    }
+    assert_eq!(legacy_tokens, current_tokens, "both lexers should produce the same tokens");
+
+    report("baseline", &source, legacy, legacy_tokens);
+    report("current", &source, current, current_tokens);
+    println!("Speedup: {:.2}x", legacy.as_secs_f64() / current.as_secs_f64());
+}
@@ -0,0 +1,620 @@
+// Stable diagnostic codes. Codes are never reused or renumbered once released,
+// so tools can match on them even when the wording of a message changes.
+//
+// R01xx: lexical errors
+// R02xx: syntax errors
+// R03xx: compilation errors
+// R04xx: file and command line errors
+
+pub const INVALID_CHARACTER: &str = "R0101";
+pub const UNTERMINATED_COMMENT: &str = "R0102";
+pub const UNTERMINATED_STRING: &str = "R0103";
+pub const INVALID_ESCAPE: &str = "R0104";
+pub const UNTERMINATED_INTERPOLATION: &str = "R0105";
+pub const MALFORMED_NUMBER: &str = "R0106";
+
+pub const UNEXPECTED_TOKEN: &str = "R0201";
+pub const EXPECTED_EXPRESSION: &str = "R0202";
+pub const EXPECTED_IDENTIFIER: &str = "R0203";
+pub const INVALID_OS: &str = "R0204";
+pub const INVALID_ARGUMENT_LIST: &str = "R0205";
+pub const INVALID_OPERATOR: &str = "R0206";          // Retired, no longer emitted
+pub const INVALID_CALL_TARGET: &str = "R0207";
+pub const EXPECTED_PARAMETER: &str = "R0208";
+
+pub const EXPRESSION_NOT_CALL: &str = "R0301";
+pub const UNSUPPORTED_VARIABLE_TYPE: &str = "R0302";
+pub const UNSUPPORTED_CONDITION_TYPE: &str = "R0303";
+pub const INVALID_PARAMETER: &str = "R0304";         // Retired, no longer emitted
+pub const UNSUPPORTED_ARGUMENT: &str = "R0305";
+pub const ARGUMENT_COUNT: &str = "R0306";
+pub const PATH_ARGUMENT_REQUIRED: &str = "R0307";
+pub const CONCAT_ARGUMENT_REQUIRED: &str = "R0308";
+pub const INVALID_ARGUMENT_TYPE: &str = "R0309";
+pub const UNSUPPORTED_OPERATOR: &str = "R0310";
+pub const MISSING_CONDITION_TYPE: &str = "R0311";
+pub const UNREPRESENTABLE_STRING: &str = "R0312";
+pub const LOGICAL_OPERATOR_OUTSIDE_CONDITION: &str = "R0313";
+pub const FLOAT_NOT_ALLOWED: &str = "R0314";
+pub const INDEX_NOT_SUPPORTED: &str = "R0315";
+pub const UNDECLARED_VARIABLE: &str = "R0316";
+pub const RETURN_OUTSIDE_FUNCTION: &str = "R0317";
+pub const RETURN_TYPE_MISMATCH: &str = "R0318";
+pub const INVALID_RANGE: &str = "R0319";
+pub const LOOP_CONTROL_OUTSIDE_LOOP: &str = "R0320";
+pub const CALL_IN_SHORT_CIRCUIT: &str = "R0321";
+
+pub const INPUT_NOT_READABLE: &str = "R0401";
+pub const OUTPUT_NOT_WRITABLE: &str = "R0402";
+pub const UNSUPPORTED_TARGET: &str = "R0403";
+
+// Long-form explanations shown by `rosella explain <CODE>`
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (INVALID_CHARACTER, r#"An unexpected character was found in the source.
+
+Rosella only accepts the punctuation used by its grammar. Any other
+character, such as '~', '$' or '@', has to be placed inside a string.
+
+Erroneous example:
+
+    let str price = $5;
+
+Fixed example:
+
+    let str price = "$5";
+"#),
+    (UNTERMINATED_COMMENT, r#"A block comment was opened with '/*' but never closed.
+
+Everything after the opening '/*' up to the end of the file is treated as
+part of the comment, so the rest of the script would silently disappear.
+
+Erroneous example:
+
+    /* Create the build folder
+    make_dir("build")
+
+Fixed example:
+
+    /* Create the build folder */
+    make_dir("build")
+"#),
+    (UNTERMINATED_STRING, r#"A string was opened with '"' but never closed.
+
+Strings cannot span past the end of the file. A quote inside a string has
+to be escaped as '\"', otherwise it ends the string early. Multi-line raw
+strings opened with '"""' must likewise be closed with '"""'.
+
+Erroneous example:
+
+    print("Hello, world)
+
+Fixed example:
+
+    print("Hello, world")
+"#),
+    (INVALID_ESCAPE, r#"A string contains a backslash escape that Rosella does not recognise.
+
+The supported escapes are '\n' (newline), '\t' (tab), '\r' (carriage
+return), '\"' (quote), '\\' (backslash), '\$' (dollar sign, to write a
+literal '${') and '\u{...}' (a unicode code point written as 1 to 6 hex
+digits). A literal backslash, such as in a Windows path, has to be
+written as '\\'.
+
+Erroneous example:
+
+    cd("C:\Users")
+
+Fixed example:
+
+    cd("C:\\Users")
+"#),
+    (UNTERMINATED_INTERPOLATION, r#"An interpolation was opened with '${' but not closed before the end of the string.
+
+Everything between '${' and '}' is read as an expression, so the closing
+'}' has to come before the string's closing quote. To write a literal
+'${', escape the dollar sign as '\$'.
+
+Erroneous example:
+
+    print("Hello ${name")
+
+Fixed example:
+
+    print("Hello ${name}")
+"#),
+    (MALFORMED_NUMBER, r#"A number literal is not written correctly.
+
+Integers are written in decimal, in hexadecimal with a '0x' prefix or in
+binary with a '0b' prefix, and must fit in a signed 64-bit integer. Floats
+are decimal digits with a single '.', such as 1.5. Underscores can be used
+between digits to group them, as in 1_000_000.
+
+Erroneous example:
+
+    let str version = 1.2.3;
+
+Fixed example:
+
+    let str version = "1.2.3";
+"#),
+    (UNEXPECTED_TOKEN, r#"The parser expected a specific token but found something else.
+
+This is most often a missing ';' after a 'let' or raw instruction, or a
+missing closing brace.
+
+Erroneous example:
+
+    let int count = 0
+    print(count)
+
+Fixed example:
+
+    let int count = 0;
+    print(count)
+"#),
+    (EXPECTED_EXPRESSION, r#"An expression was expected but the next token cannot start one.
+
+Expressions are numbers, strings, variables, function calls and binary
+operations between them, optionally wrapped in parentheses.
+
+Erroneous example:
+
+    let int total = ;
+
+Fixed example:
+
+    let int total = 0;
+"#),
+    (EXPECTED_IDENTIFIER, r#"A name was expected but something else was found.
+
+'let' needs a type and a variable name, 'fn' needs a function name, and
+'if'/'while' need a comparison type before their condition.
+
+Erroneous example:
+
+    let int = 5;
+    if (x < 5) { print("small") }
+
+Fixed example:
+
+    let int x = 5;
+    if int(x < 5) { print("small") }
+"#),
+    (INVALID_OS, r#"The 'with' statement was given an unknown operating system.
+
+Only 'windows' and 'linux' are supported. The block is only compiled when
+it matches the target OS.
+
+Erroneous example:
+
+    with macos {
+        print("Hello from macOS")
+    }
+
+Fixed example:
+
+    with linux {
+        print("Hello from Linux")
+    }
+"#),
+    (INVALID_ARGUMENT_LIST, r#"An argument list is missing a ',' or the closing ')'.
+
+Arguments to a function call or definition must be separated by commas.
+
+Erroneous example:
+
+    print("Result: " result)
+
+Fixed example:
+
+    print("Result: ", result)
+"#),
+    (INVALID_OPERATOR, r#"A token was treated as a binary operator but has no meaning as one.
+
+This error is no longer emitted. Every binary operator is now read from a
+single table that maps each token straight to its operator, so the parser
+can no longer pick up a token it has no operator for. The code is kept so
+it is never given to a different error.
+
+The binary operators are +, -, *, /, %, ==, !=, <, <=, >, >=, && and ||.
+"#),
+    (INVALID_CALL_TARGET, r#"Something other than a function name was followed by '('.
+
+Only functions can be called, and a call is written as the function's
+name followed by its arguments in parentheses. Strings, numbers and the
+result of another call cannot be called.
+
+Erroneous example:
+
+    greet()("world")
+
+Fixed example:
+
+    greet("world")
+"#),
+    (EXPECTED_PARAMETER, r#"A function parameter is not a plain name.
+
+Function definitions list the names their arguments are bound to, each
+optionally preceded by its type. Values belong at the call site, not in
+the definition.
+
+Erroneous example:
+
+    fn greet("world") {
+        print("Hello")
+    }
+
+Fixed example:
+
+    fn greet(str name) {
+        print("Hello ", name)
+    }
+"#),
+    (EXPRESSION_NOT_CALL, r#"An expression was used as a statement, but it is not a function call.
+
+A bare value such as a number, string or variable does nothing on its own.
+Statements must either be a function call or start with a keyword.
+
+Erroneous example:
+
+    x + 1
+
+Fixed example:
+
+    let int y = x + 1;
+"#),
+    (UNSUPPORTED_VARIABLE_TYPE, r#"A 'let' statement used a type the target shell cannot represent.
+
+The supported variable types are 'int', 'float', 'str' and 'bool'.
+
+Erroneous example:
+
+    let number count = 0;
+
+Fixed example:
+
+    let int count = 0;
+"#),
+    (UNSUPPORTED_CONDITION_TYPE, r#"An 'if' or 'while' used a comparison type that is not supported.
+
+Batch and Bash compare numbers and text with different operators, so every
+condition is tagged with the type of the values being compared: 'int',
+'float' or 'str'. Conditions built only from bool variables, 'true', 'false' and
+checks such as exists() are tagged 'bool'.
+
+Erroneous example:
+
+    if text(name == "admin") { print("Welcome back") }
+
+Fixed example:
+
+    if str(name == "admin") { print("Welcome back") }
+"#),
+    (INVALID_PARAMETER, r#"A function parameter is not a plain name.
+
+This error is no longer emitted. Parameters are now read by the parser,
+which reports anything other than a name as R0208.
+"#),
+    (UNSUPPORTED_ARGUMENT, r#"A value was passed somewhere that cannot accept it.
+
+Function calls, print/echo, paths, concat() and raw instructions only
+accept strings, numbers and variables. Calculations have to be stored in a
+variable first.
+
+Erroneous example:
+
+    print("Total: ", x + 1)
+
+Fixed example:
+
+    let int total = x + 1;
+    print("Total: ", total)
+"#),
+    (ARGUMENT_COUNT, r#"A function was called with the wrong number of arguments.
+
+For example copy/cp and move/mv take exactly a source and a destination,
+read takes a prompt and a variable, and get_cwd takes nothing. Functions
+declared with 'fn' take exactly the parameters they list.
+
+Erroneous example:
+
+    cp(path("notes.txt"))
+
+Fixed example:
+
+    cp(path("notes.txt"), path("backup", "notes.txt"))
+"#),
+    (PATH_ARGUMENT_REQUIRED, r#"A built-in function requires path() as argument.
+
+copy/cp, move/mv, write_file and append_file format their paths for the
+target OS, using '\' on Windows and '/' on Linux. To do that they need
+every path to be wrapped in path().
+
+Erroneous example:
+
+    cp("notes.txt", "backup.txt")
+
+Fixed example:
+
+    cp(path("notes.txt"), path("backup.txt"))
+"#),
+    (CONCAT_ARGUMENT_REQUIRED, r#"write_file/append_file require a string or concat() as their content.
+
+The content written to a file is either a string, which can interpolate
+variables with '${name}', or a concat() joining strings, numbers and
+variables into one value.
+
+Erroneous example:
+
+    write_file(path("log.txt"), status)
+
+Fixed example:
+
+    write_file(path("log.txt"), "${status}")
+"#),
+    (INVALID_ARGUMENT_TYPE, r#"A function was given an argument of the wrong kind.
+
+read takes a prompt string followed by the variable to store the answer
+in, and exit takes a numeric exit code. A parameter declared with a type,
+such as 'int count', only accepts values of that type, except that an
+int can be passed where a float is expected.
+
+Erroneous example:
+
+    read(name, "What is your name?")
+
+Fixed example:
+
+    read("What is your name?", name)
+"#),
+    (UNSUPPORTED_OPERATOR, r#"An operator is not supported for this comparison type on the target shell.
+
+Batch can only test strings for equality, and neither shell can order
+strings with '<=' or '>='. Ordering comparisons are available for 'int'.
+
+Erroneous example:
+
+    if str(name <= "admin") { print("Access denied") }
+
+Fixed example:
+
+    if str(name != "admin") { print("Access denied") }
+"#),
+    (MISSING_CONDITION_TYPE, r#"An operator was used where no type is known.
+
+Operators compile differently for numbers and text, so they are only
+allowed inside a typed 'let', 'if' or 'while'.
+
+Erroneous example:
+
+    |> echo x + 1;
+
+Fixed example:
+
+    let int next = x + 1;
+    print(next)
+"#),
+    (UNREPRESENTABLE_STRING, r#"A string cannot be written in the target shell.
+
+Batch has no way to put a line break inside a quoted argument or a 'set'
+value. Line breaks are only supported in strings passed to 'print', which
+prints each line with its own 'echo'.
+
+Erroneous example:
+
+    with windows {
+        let str greeting = "Hello\nWorld";
+    }
+
+Fixed example:
+
+    with windows {
+        print("Hello\nWorld")
+    }
+"#),
+    (LOGICAL_OPERATOR_OUTSIDE_CONDITION, r#"A logical operator was used outside a condition.
+
+'&&', '||' and '!' combine conditions. Neither shell has a boolean value,
+so they are only allowed inside the condition of an 'if' or 'while', or
+as the value of a 'let bool', which stores the result as 1 or 0.
+
+Erroneous example:
+
+    let int both = a > 0 && b > 0;
+
+Fixed example:
+
+    let bool both = a > 0 && b > 0;
+"#),
+    (FLOAT_NOT_ALLOWED, r#"A float literal or variable was used where only whole numbers are supported.
+
+Batch arithmetic with 'set /a' and Bash arithmetic with $(( )) only work
+on integers, so 'int' variables and conditions cannot hold a fraction.
+Use a 'float' variable or condition instead, which is calculated with awk
+on Bash and PowerShell on Batch.
+
+Erroneous example:
+
+    let int ratio = 1.5;
+
+Fixed example:
+
+    let float ratio = 1.5;
+"#),
+    (INDEX_NOT_SUPPORTED, r#"An index expression such as 'values[0]' was used.
+
+Rosella has no arrays or lists yet, so there is nothing that can be
+indexed. Store each value in its own variable instead.
+
+Erroneous example:
+
+    let int first = values[0];
+
+Fixed example:
+
+    let int first = first_value;
+"#),
+    (UNDECLARED_VARIABLE, r#"A variable was assigned to before it was declared.
+
+Assignments such as 'x = 1;', 'x += 1;' and 'x++;' update a variable that
+already exists, and keep the type it was declared with. The first value
+of a variable has to be given with 'let', together with its type.
+
+Erroneous example:
+
+    count = 0;
+
+Fixed example:
+
+    let int count = 0;
+"#),
+    (RETURN_OUTSIDE_FUNCTION, r#"'return' was used outside of a function.
+
+'return' ends the function it is written in and hands its value back to
+the caller. At the top level of a script there is no caller to return
+to. Use exit() to end the script early instead.
+
+Erroneous example:
+
+    if str(name == "") {
+        return;
+    }
+
+Fixed example:
+
+    if str(name == "") {
+        exit(1)
+    }
+"#),
+    (RETURN_TYPE_MISMATCH, r#"A 'return' does not match the return type of its function.
+
+A function declared with a return type, such as 'fn add(int x, int y) ->
+int', has to hand back a value of that type from every 'return'. An int
+can be returned where a float is declared.
+
+Erroneous example:
+
+    fn half(int x) -> int {
+        return 0.5;
+    }
+
+Fixed example:
+
+    fn half(float x) -> float {
+        return x / 2;
+    }
+"#),
+    (INVALID_RANGE, r#"A 'for' range is not made of whole numbers, or cannot be counted.
+
+'for i in start..end' counts from start up to, but not including, end.
+Both ends have to be ints. The optional 'step' has to be a number written
+in the script, other than 0, so the direction of the loop is known when
+compiling. Use a negative step, such as 'step -1', to count down. An end
+at the very limit of an int cannot be counted to either.
+
+Erroneous example:
+
+    for i in 0..2.5 {
+        print(i)
+    }
+
+Fixed example:
+
+    for i in 0..3 {
+        print(i)
+    }
+"#),
+    (LOOP_CONTROL_OUTSIDE_LOOP, r#"'break' or 'continue' was used outside of a loop.
+
+'break' leaves the innermost 'while' or 'for' loop, and 'continue' skips
+to its next iteration. Outside of a loop there is nothing for them to act
+on. A loop does not reach into the functions called from its body, so a
+function has to return instead.
+
+Erroneous example:
+
+    fn check(int attempt) {
+        if int(attempt > 3) {
+            break;
+        }
+    }
+
+Fixed example:
+
+    fn check(int attempt) -> bool {
+        return attempt > 3;
+    }
+"#),
+    (CALL_IN_SHORT_CIRCUIT, r#"A function was called on the right of '&&' or '||'.
+
+The right of '&&' is only evaluated when the left is true, and the right
+of '||' only when the left is false. Calls to functions run before the
+condition is tested, so one placed there would run every time. Call the
+function in its own statement first, or move it to the left, where it is
+always evaluated anyway.
+
+Erroneous example:
+
+    if bool(count > 5 && is_ready(count)) {
+        print("Ready")
+    }
+
+Fixed example:
+
+    if bool(count > 5) {
+        if bool(is_ready(count)) {
+            print("Ready")
+        }
+    }
+"#),
+    (INPUT_NOT_READABLE, r#"The input file could not be read.
+
+The path given to --input has to name an existing file that the compiler
+is allowed to read, and its contents have to be valid UTF-8. The message
+carries the reason reported by the operating system.
+
+Erroneous example:
+
+    rosella compile --input missing.rosella
+
+Fixed example:
+
+    rosella compile --input build.rosella
+"#),
+    (OUTPUT_NOT_WRITABLE, r#"The compiled script could not be written.
+
+The folder the output goes into has to exist and be writable. Without
+--output the script is written next to the input file, with the extension
+of the target shell. The message carries the reason reported by the
+operating system.
+
+Erroneous example:
+
+    rosella compile --input build.rosella --output missing/build.sh
+
+Fixed example:
+
+    rosella compile --input build.rosella --output build.sh
+"#),
+    (UNSUPPORTED_TARGET, r#"The target OS and shell cannot be combined.
+
+Batch scripts only run on Windows, so they cannot be produced for Linux.
+When --target or --shell is left out, it falls back to the OS or shell of
+the machine running the compiler.
+
+Erroneous example:
+
+    rosella compile --input build.rosella --target linux --shell batch
+
+Fixed example:
+
+    rosella compile --input build.rosella --target windows --shell batch
+"#),
+];
+
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
@@ -1,14 +1,65 @@
-use super::parser::BinaryOp;
-use super::parser::Expr;
-use super::parser::Stmt;
+use std::collections::HashMap;
+
+use super::parser::{AssignOp, BinaryOp, UnaryOp};
+use super::parser::{Expr, ExprKind, InterpolatedPart};
+use super::parser::{Param, Stmt, StmtKind};
 use super::parser::OS;
-use super::error::RosellaError;
+use super::codes;
+use super::diagnostic::Diagnostic;
+use super::span::Span;
 
 pub struct Compiler {
     statements: Vec<Stmt>,
     unique_index: usize,
     os: OS,
     shell: Shell,
+
+    // Declared type of each variable, used where a comparison has no type of its own
+    variables: HashMap<String, String>,
+
+    // Decimal places kept by float arithmetic
+    float_precision: usize,
+
+    // Signature of every user function, collected up front so calls can be checked wherever they are
+    functions: HashMap<String, Signature>,
+
+    // Type of the value each user function returns, where its signature or 'return' statements make it known
+    return_types: HashMap<String, String>,
+
+    // Function whose body is being compiled
+    current_function: Option<String>,
+
+    // Letters naming the variables of the Batch 'for' loops currently open
+    loop_letters: Vec<char>,
+
+    // Where 'continue' and 'break' jump to in each enclosing loop, innermost last.
+    // Bash has the keywords themselves, so its loops have no labels
+    loops: Vec<Option<LoopLabels>>,
+
+    // Batch function bodies, written after the main script so running it never falls into them
+    function_definitions: String,
+
+    // Script variables the current function assigns without declaring them itself
+    function_exports: Vec<String>,
+}
+
+struct LoopLabels {
+    next: String,
+    end: String,
+}
+
+// Functions provided by the compiler itself, which are expanded inline rather than called
+const STD_FUNCTIONS: &[&str] = &[
+    "cd", "print", "echo", "make_dir", "mkdir", 
+    "remove_dir", "rmdir", "remove", "del",
+    "path", "copy", "cp", "move", "mv", 
+    "write_file", "append_file", "get_cwd", "read",
+    "exit", "exists", "not_exists", "concat"
+];
+
+struct Signature {
+    parameters: Vec<Param>,
+    return_type: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -24,16 +75,30 @@ impl Compiler {
             unique_index: 0,
             os,
             shell,
+            variables: HashMap::new(),
+            float_precision: 2,
+            functions: HashMap::new(),
+            return_types: HashMap::new(),
+            current_function: None,
+            loop_letters: Vec::new(),
+            loops: Vec::new(),
+            function_definitions: String::new(),
+            function_exports: Vec::new(),
         }
     }
 
+    pub fn float_precision(mut self, precision: usize) -> Self {
+        self.float_precision = precision;
+        self
+    }
+
     fn next_unique_index(&mut self) -> usize {
         let index = self.unique_index;
         self.unique_index += 1;
         index
     }
 
-    pub fn compile(&mut self) -> Result<String, RosellaError> {
+    pub fn compile(&mut self) -> Result<String, Diagnostic> {
         let mut output = String::new();
 
         match self.shell {
@@ -44,55 +109,306 @@ impl Compiler {
             Shell::Bash => output.push_str("#!/bin/bash\n"),
         }
 
-        for statement in &self.statements.clone() {
-            output.push_str(&self.compile_statement(&statement)?);
+        let statements = self.statements.clone();
+        self.collect_signatures(&statements);
+
+        for statement in &statements {
+            output.push_str(&self.compile_statement(statement)?);
+        }
+
+        if !self.function_definitions.is_empty() {
+            output.push_str("goto :eof\n");
+            output.push_str(&self.function_definitions);
         }
 
         Ok(output)
     }
 
-    fn compile_statement(&mut self, statement: &Stmt) -> Result<String, RosellaError> {
-        match statement {
-            Stmt::Let {name, value, variable_type} => Ok(self.compile_let_stmt(name, value, variable_type, statement)?),
-            Stmt::If {condition, then_branch, else_branch, .. } 
-                => Ok(self.compile_if_stmt(condition, then_branch, else_branch.as_ref(), statement)?),
-            Stmt::With {os, body} => Ok(self.compile_with_stmt(*os, body)?), 
-            Stmt::While {condition, body, ..} 
-                => Ok(self.compile_while_stmt(condition, body, statement)?),
-            Stmt::Function {name, arguments, body} 
-                => Ok(self.compile_function(name, arguments, body)?),
-            Stmt::Expression(expr) => {
-                let (name, args) = match expr {
-                    Expr::Call { name, args} => (name, args),
-                    _ => return Err(RosellaError::CompilerError("Expression is not a function call".to_string())),
+    fn collect_signatures(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            match &statement.kind {
+                StmtKind::Function { name, parameters, return_type, body } => {
+                    if let Some(return_type) = return_type {
+                        self.return_types.insert(name.clone(), return_type.clone());
+                    }
+                    self.functions.insert(name.clone(), Signature { parameters: parameters.clone(), return_type: return_type.clone() });
+                    self.collect_signatures(body);
+                }
+                StmtKind::If { then_branch, else_branch, .. } => {
+                    self.collect_signatures(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.collect_signatures(else_branch);
+                    }
+                }
+                StmtKind::With { os, body } if *os == self.os => self.collect_signatures(body),
+                StmtKind::While { body, .. } | StmtKind::For { body, .. } => self.collect_signatures(body),
+                _ => {}
+            }
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Stmt) -> Result<String, Diagnostic> {
+        let mut output = self.compile_comments(&statement.comments);
+        output.push_str(&self.compile_statement_kind(statement)?);
+        output.push_str(&self.compile_comments(&statement.trailing_comments));
+
+        // Batch functions are moved after the main script, along with their comments
+        if self.shell == Shell::Batch && matches!(statement.kind, StmtKind::Function { .. }) {
+            self.function_definitions.push_str(&output);
+            return Ok(String::new());
+        }
+
+        Ok(output)
+    }
+
+    fn compile_comments(&self, comments: &[String]) -> String {
+        let mut output = String::new();
+
+        for comment in comments {
+            // Block comments are often decorated with a leading '*' on each line
+            let lines: Vec<&str> = comment
+                .lines()
+                .map(|line| line.trim())
+                .map(|line| line.strip_prefix('*').unwrap_or(line).trim())
+                .collect();
+
+            // Drop the blank lines that pad out block comments
+            let first = lines.iter().position(|line| !line.is_empty());
+            let last = lines.iter().rposition(|line| !line.is_empty());
+            let lines = match (first, last) {
+                (Some(first), Some(last)) => &lines[first..=last],
+                _ => continue,
+            };
+
+            for line in lines {
+                let prefix = match self.shell {
+                    Shell::Batch => "REM",
+                    Shell::Bash => "#",
+                };
+
+                if line.is_empty() {
+                    output.push_str(&format!("{}\n", prefix));
+                }
+                else {
+                    output.push_str(&format!("{} {}\n", prefix, line));
+                }
+            }
+        }
+
+        output
+    }
+
+    fn compile_statement_kind(&mut self, statement: &Stmt) -> Result<String, Diagnostic> {
+        match &statement.kind {
+            StmtKind::Let {name, value, variable_type} => {
+                let (mut output, value) = self.hoist_calls(value)?;
+                output.push_str(&self.compile_let_stmt(name, &value, variable_type, statement)?);
+                Ok(output)
+            }
+            StmtKind::Assign {name, operator, value} => {
+                let (mut output, value) = match value {
+                    Some(value) => {
+                        let (prelude, value) = self.hoist_calls(value)?;
+                        (prelude, Some(value))
+                    }
+                    None => (String::new(), None),
                 };
+                output.push_str(&self.compile_assign_stmt(name, *operator, value.as_ref(), statement)?);
+                Ok(output)
+            }
+            StmtKind::If {condition, then_branch, else_branch, .. } 
+                => Ok(self.compile_if_stmt(condition, then_branch, else_branch.as_deref(), statement)?),
+            StmtKind::With {os, body} => Ok(self.compile_with_stmt(*os, body)?), 
+            StmtKind::While {condition, body, ..} 
+                => Ok(self.compile_while_stmt(condition, body, statement)?),
+            StmtKind::For {variable, start, end, step, body}
+                => Ok(self.compile_for_stmt(variable, start, end, step.as_ref(), body, statement)?),
+            StmtKind::Function {name, parameters, return_type, body} 
+                => Ok(self.compile_function(name, parameters, return_type.as_ref(), body, statement)?),
+            StmtKind::Expression(expr) => {
+                if !matches!(expr.kind, ExprKind::Call { .. }) {
+                    return Err(Diagnostic::error(codes::EXPRESSION_NOT_CALL, format!("Expected a function call, found {}", expr.kind.description()), expr.span)
+                        .with_note("only function calls can be used as statements"));
+                }
 
-                Ok(self.compile_function_call(name, args)?)
+                // A call to a user function is hoisted whole, which leaves nothing else to compile
+                let (mut output, expr) = self.hoist_calls(expr)?;
+                if let ExprKind::Call { name, args } = &expr.kind {
+                    output.push_str(&self.compile_function_call(name, args, expr.span)?);
+                }
+                Ok(output)
             }
-            Stmt::RawInstruction(instructions) => Ok(self.compile_raw_instruction(instructions, statement)?),
+            StmtKind::Break | StmtKind::Continue => Ok(self.compile_loop_control(statement)?),
+            StmtKind::Return(value) => Ok(self.compile_return_stmt(value.as_ref(), statement)?),
+            StmtKind::RawInstruction(instructions) => Ok(self.compile_raw_instruction(instructions, statement)?),
         }
     }
 
-    fn compile_let_stmt(&self, name: &String, value: &Expr, variable_type: &String, parent_statement: &Stmt) -> Result<String, RosellaError> {
+    fn compile_let_stmt(&mut self, name: &String, value: &Expr, variable_type: &String, parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        check_variable_type("variable", variable_type, parent_statement.span)?;
+
+        self.variables.insert(name.clone(), variable_type.clone());
+
+        if variable_type == "bool" {
+            return self.compile_bool_let_stmt(name, value, parent_statement);
+        }
+        if variable_type == "float" {
+            return self.compile_float_let_stmt(name, value);
+        }
+
+        let value_str = self.compile_expr(value, parent_statement)?;
+
         match self.shell {
             Shell::Batch => {
-                let value_str = self.compile_expr(value, parent_statement)?;
                 match variable_type.as_str() {
                     "int" => Ok(format!("set /a {}={}\n", name, value_str)),
-                    "str" => Ok(format!("set \"{}={}\"\n", name, value_str)),
-                    _ => Err(RosellaError::CompilerError(format!("Unsupported variable type: {}", variable_type))),
+                    _ => Ok(format!("set \"{}={}\"\n", name, value_str)),
+                }
+            },
+            Shell::Bash => Ok(format!("{}={}\n", name, value_str)),
+        }
+    }
+
+    // Updates a variable declared earlier, keeping its declared type
+    fn compile_assign_stmt(&mut self, name: &String, operator: AssignOp, value: Option<&Expr>, parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        let variable_type = match self.variables.get(name) {
+            Some(variable_type) => variable_type.clone(),
+            None => return Err(Diagnostic::error(codes::UNDECLARED_VARIABLE, format!("Cannot assign to '{}' before it is declared", name), parent_statement.span)
+                .with_help(format!("declare it first with 'let', e.g. let int {} = 0;", name))),
+        };
+
+        // '++' and '--' step by one
+        let one = Expr::new(ExprKind::Int(1), parent_statement.span);
+        let value = value.unwrap_or(&one);
+
+        let typed_statement = Stmt::new(StmtKind::Let {
+            variable_type: variable_type.clone(),
+            name: name.clone(),
+            value: value.clone(),
+        }, parent_statement.span);
+
+        let Some(arithmetic) = operator.binary_op() else {
+            return self.compile_let_stmt(name, value, &variable_type, &typed_statement);
+        };
+
+        match variable_type.as_str() {
+            "int" => {
+                let value_str = self.compile_expr(value, &typed_statement)?;
+                let operator_str = self.format_operator(arithmetic, &typed_statement)?;
+
+                match self.shell {
+                    Shell::Batch => Ok(format!("set /a {}{}={}\n", name, operator_str, value_str)),
+                    Shell::Bash => Ok(format!("(({}{}={}))\n", name, operator_str, value_str)),
+                }
+            }
+            "float" => {
+                // Float maths goes through awk or PowerShell, so the update is written out in full
+                let variable = Expr::new(ExprKind::Identifier(name.clone()), parent_statement.span);
+                let updated = Expr::new(ExprKind::Binary {
+                    left: Box::new(variable),
+                    operator: arithmetic,
+                    right: Box::new(value.clone()),
+                }, parent_statement.span);
+
+                self.compile_float_let_stmt(name, &updated)
+            }
+            "str" if operator == AssignOp::Add => {
+                let value_str = self.compile_expr(value, &typed_statement)?;
+
+                match self.shell {
+                    Shell::Batch => Ok(format!("set \"{}=!{}!{}\"\n", name, name, value_str)),
+                    Shell::Bash => Ok(format!("{}+={}\n", name, value_str)),
                 }
+            }
+            _ => {
+                let help = match variable_type.as_str() {
+                    "str" => "strings can only be assigned with '=' or appended to with '+='",
+                    _ => "bools can only be assigned with '='",
+                };
+                Err(Diagnostic::error(codes::UNSUPPORTED_OPERATOR, format!("Cannot use '{}' on the {} variable '{}'", operator, variable_type, name), parent_statement.span)
+                    .with_help(help))
+            }
+        }
+    }
+
+    // Shell arithmetic is integer only, so float maths is done by awk on Bash and PowerShell on Batch.
+    // Every float is stored with the same number of decimal places, so values read the same
+    // on either target however they were worked out
+    fn compile_float_let_stmt(&self, name: &str, value: &Expr) -> Result<String, Diagnostic> {
+        let value_str = self.compile_float_expr(value)?;
+
+        // Values that need no arithmetic are written out with the decimal places already in place
+        let zeros = if self.float_precision > 0 { format!(".{}", "0".repeat(self.float_precision)) } else { String::new() };
+        let stored = match &value.kind {
+            ExprKind::Int(n) => Some(format!("{}{}", n, zeros)),
+            ExprKind::Float(n) => Some(format!("{:.*}", self.float_precision, n)),
+            ExprKind::Identifier(id) => match self.variables.get(id).map(String::as_str) {
+                Some("float") => Some(value_str.clone()),
+                Some("int") => Some(format!("{}{}", value_str, zeros)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some(stored) = stored {
+            return match self.shell {
+                Shell::Batch => Ok(format!("set \"{}={}\"\n", name, stored)),
+                Shell::Bash => Ok(format!("{}={}\n", name, stored)),
+            };
+        }
+
+        match self.shell {
+            Shell::Batch => Ok(format!(
+                "for /f \"delims=\" %%v in ('powershell -NoProfile -Command \"({}).ToString('F{}', [cultureinfo]::InvariantCulture)\"') do set \"{}=%%v\"\n",
+                value_str, self.float_precision, name)),
+            Shell::Bash => Ok(format!(
+                "{}=$(awk \"BEGIN {{ printf \\\"%.{}f\\\", {} }}\")\n",
+                name, self.float_precision, value_str)),
+        }
+    }
+
+    // Bools are stored as 1 or 0, so conditions are evaluated and their result assigned
+    fn compile_bool_let_stmt(&mut self, name: &str, value: &Expr, parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        let stored = match &value.kind {
+            ExprKind::Boolean(value) => Some(u8::from(*value).to_string()),
+            ExprKind::Identifier(id) => match self.shell {
+                Shell::Batch => Some(format!("!{}!", id)),
+                Shell::Bash => Some(format!("${{{}}}", id)),
             },
+            _ => None,
+        };
+
+        if let Some(stored) = stored {
+            return match self.shell {
+                Shell::Batch => Ok(format!("set /a {}={}\n", name, stored)),
+                Shell::Bash => Ok(format!("{}={}\n", name, stored)),
+            };
+        }
+
+        let (prelude, condition_str) = self.compile_condition(value, parent_statement)?;
+        let mut output = prelude;
+
+        match self.shell {
+            Shell::Batch => {
+                output.push_str(&format!("set /a {}=0\n", name));
+                output.push_str(&format!("if {} set /a {}=1\n", condition_str, name));
+            }
             Shell::Bash => {
-                let value_str = self.compile_expr(value, parent_statement)?;
-                Ok(format!("{}={}\n", name, value_str))
+                output.push_str(&format!("if [[ {} ]]; then\n", condition_str));
+                output.push_str(&indent(format!("{}=1\n", name)));
+                output.push_str("else\n");
+                output.push_str(&indent(format!("{}=0\n", name)));
+                output.push_str("fi\n");
             }
         }
+
+        Ok(output)
     }
 
-    fn compile_if_stmt(&mut self, condition: &Expr, then_branch: &Vec<Stmt>, else_branch: Option<&Vec<Stmt>>, parent_statement: &Stmt) -> Result<String, RosellaError> {
-        let condition_str = self.compile_expr(condition, parent_statement)?;
-        let mut output = String::new();
+    fn compile_if_stmt(&mut self, condition: &Expr, then_branch: &[Stmt], else_branch: Option<&[Stmt]>, parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        let (mut output, condition) = self.hoist_calls(condition)?;
+        let (prelude, condition_str) = self.compile_condition(&condition, parent_statement)?;
+        output.push_str(&prelude);
 
         match self.shell {
             Shell::Batch => {
@@ -126,7 +442,7 @@ impl Compiler {
         Ok(output)
     }
 
-    fn compile_with_stmt(&mut self, os: OS, body: &Vec<Stmt>) -> Result<String, RosellaError> {
+    fn compile_with_stmt(&mut self, os: OS, body: &[Stmt]) -> Result<String, Diagnostic> {
         let mut output = String::new();
 
         match (self.os, os) {
@@ -146,8 +462,11 @@ impl Compiler {
         Ok(output)
     }
 
-    fn compile_while_stmt(&mut self, condition: &Expr, body: &Vec<Stmt>, parent_statement: &Stmt) -> Result<String, RosellaError> {
-        let condition_str = self.compile_expr(condition, parent_statement)?;
+    fn compile_while_stmt(&mut self, condition: &Expr, body: &[Stmt], parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        // The condition is re-evaluated after the loop label, on every iteration
+        let (mut prelude, condition) = self.hoist_calls(condition)?;
+        let (condition_prelude, condition_str) = self.compile_condition(&condition, parent_statement)?;
+        prelude.push_str(&condition_prelude);
         let mut output = String::new();
 
         match self.shell {
@@ -157,11 +476,14 @@ impl Compiler {
                 let loop_start_label = format!("while_loop_{}", index);
                 let loop_end_label = format!("while_end_{}", index);
                 output.push_str(&format!(":{}\n", loop_start_label));
+                output.push_str(&indent(prelude));
 
                 output.push_str(&indent(format!("if {} (\n", condition_str)));
+                self.loops.push(Some(LoopLabels { next: loop_start_label.clone(), end: loop_end_label.clone() }));
                 for stmt in body {
-                    output.push_str(&format!("      {}", &self.compile_statement(stmt)?));
+                    output.push_str(&indent(indent(self.compile_statement(stmt)?)));
                 }
+                self.loops.pop();
                 output.push_str(&format!("      goto :{}\n", loop_start_label));
                 output.push_str(&indent(") else (\n"));
 
@@ -171,10 +493,133 @@ impl Compiler {
 
             },
             Shell::Bash => {
-                output.push_str(&format!("while [[ {} ]]; do\n", condition_str));
+                // Calls in the condition have to run before every test, so they go in the condition list
+                if prelude.is_empty() {
+                    output.push_str(&format!("while [[ {} ]]; do\n", condition_str));
+                }
+                else {
+                    output.push_str("while\n");
+                    output.push_str(&indent(prelude));
+                    output.push_str(&indent(format!("[[ {} ]]\n", condition_str)));
+                    output.push_str("do\n");
+                }
+                self.loops.push(None);
                 for stmt in body {
                     output.push_str(&indent(&self.compile_statement(stmt)?));
                 }
+                self.loops.pop();
+                output.push_str("done\n");
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn compile_for_stmt(&mut self, variable: &str, start: &Expr, end: &Expr, step: Option<&Expr>, body: &[Stmt], parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        let one = Expr::new(ExprKind::Int(1), parent_statement.span);
+        let step = step.unwrap_or(&one);
+
+        for bound in [start, end, step] {
+            if let Some(found) = self.infer_type(bound) && found != "int" {
+                return Err(Diagnostic::error(codes::INVALID_RANGE, format!("Expected int in a range, found {}", found), bound.span)
+                    .with_help("ranges count in whole numbers"));
+            }
+        }
+
+        // The direction of the loop is decided when compiling, so the step has to be written out
+        let step_value = match step.kind {
+            ExprKind::Int(0) => return Err(Diagnostic::error(codes::INVALID_RANGE, "A range cannot step by 0", step.span)
+                .with_help("step by a positive number to count up, or a negative one to count down")),
+            ExprKind::Int(n) => n,
+            _ => return Err(Diagnostic::error(codes::INVALID_RANGE, "The step of a range has to be a number", step.span)
+                .with_help("write the step out, such as 'step 2' or 'step -1'")),
+        };
+        let descending = step_value < 0;
+
+        // for /l has nowhere to 'continue' to, and any goto inside it ends the loop,
+        // so on Batch such a loop counts with labels like 'while' does
+        let labelled = self.shell == Shell::Batch && needs_labels(body);
+
+        // for /l includes its end value, where a range stops just before it.
+        // The end is checked on every target so they accept the same ranges
+        if let ExprKind::Int(n) = end.kind && n.checked_sub(step_value.signum()).is_none() {
+            return Err(Diagnostic::error(codes::INVALID_RANGE, "The end of this range is too large to count to", end.span)
+                .with_help("use an end that fits in an int with a number to spare"));
+        }
+        let end = match &end.kind {
+            _ if self.shell == Shell::Bash || labelled => end.clone(),
+            ExprKind::Int(n) => Expr::new(ExprKind::Int(n - step_value.signum()), end.span),
+            _ => Expr::new(ExprKind::Binary {
+                left: Box::new(end.clone()),
+                operator: if descending { BinaryOp::Add } else { BinaryOp::Subtract },
+                right: Box::new(Expr::new(ExprKind::Int(1), end.span)),
+            }, end.span),
+        };
+
+        let index = self.next_unique_index();
+        let mut output = String::new();
+        let start = self.range_bound(start, &format!("__for_start_{}", index), &mut output)?;
+        let end = self.range_bound(&end, &format!("__for_end_{}", index), &mut output)?;
+        let step_str = step_value.to_string();
+
+        self.variables.insert(variable.to_string(), "int".to_string());
+
+        match self.shell {
+            Shell::Batch if labelled => {
+                let loop_start_label = format!("for_loop_{}", index);
+                let loop_next_label = format!("for_next_{}", index);
+                let loop_end_label = format!("for_end_{}", index);
+                let comparison = if descending { "LEQ" } else { "GEQ" };
+
+                output.push_str(&format!("set /a {}={}\n", variable, start));
+                output.push_str(&format!(":{}\n", loop_start_label));
+                output.push_str(&indent(format!("if !{}! {} {} goto :{}\n", variable, comparison, end, loop_end_label)));
+
+                self.loops.push(Some(LoopLabels { next: loop_next_label.clone(), end: loop_end_label.clone() }));
+                for stmt in body {
+                    output.push_str(&indent(self.compile_statement(stmt)?));
+                }
+                self.loops.pop();
+
+                output.push_str(&format!(":{}\n", loop_next_label));
+                output.push_str(&indent(format!("set /a {}+={}\n", variable, step_str)));
+                output.push_str(&indent(format!("goto :{}\n", loop_start_label)));
+                output.push_str(&format!(":{}\n", loop_end_label));
+            }
+            Shell::Batch => {
+                // for /l can only name its variable with a single letter, which has to differ from any enclosing loop's
+                let letter = variable.chars().next()
+                    .filter(|ch| ch.is_ascii_alphabetic() && !self.loop_letters.contains(ch))
+                    .or_else(|| ('a'..='z').find(|ch| !self.loop_letters.contains(ch)))
+                    .unwrap_or('i');
+
+                output.push_str(&format!("for /l %%{} in ({},{},{}) do (\n", letter, start, step_str, end));
+                output.push_str(&indent(format!("set /a {}=%%{}\n", variable, letter)));
+
+                self.loop_letters.push(letter);
+                self.loops.push(None);
+                for stmt in body {
+                    output.push_str(&indent(self.compile_statement(stmt)?));
+                }
+                self.loops.pop();
+                self.loop_letters.pop();
+
+                output.push_str(")\n");
+            }
+            Shell::Bash => {
+                let (comparison, update) = match step.kind {
+                    ExprKind::Int(1) => ("<", format!("{}++", variable)),
+                    ExprKind::Int(-1) => (">", format!("{}--", variable)),
+                    _ if descending => (">", format!("{}+={}", variable, step_str)),
+                    _ => ("<", format!("{}+={}", variable, step_str)),
+                };
+
+                output.push_str(&format!("for (({}={}; {}{}{}; {})); do\n", variable, start, variable, comparison, end, update));
+                self.loops.push(None);
+                for stmt in body {
+                    output.push_str(&indent(self.compile_statement(stmt)?));
+                }
+                self.loops.pop();
                 output.push_str("done\n");
             }
         }
@@ -182,36 +627,105 @@ impl Compiler {
         Ok(output)
     }
 
-    fn compile_function(&mut self, name: &str, args: &Option<Vec<Expr>>, body: &Vec<Stmt>) -> Result<String, RosellaError> {
+    fn compile_loop_control(&self, statement: &Stmt) -> Result<String, Diagnostic> {
+        let keyword = match statement.kind {
+            StmtKind::Break => "break",
+            _ => "continue",
+        };
+
+        let Some(labels) = self.loops.last() else {
+            let error = Diagnostic::error(codes::LOOP_CONTROL_OUTSIDE_LOOP, format!("'{}' can only be used inside a loop", keyword), statement.span);
+            return match self.current_function {
+                Some(_) => Err(error.with_help("use 'return' to leave the function early")),
+                None => Err(error),
+            };
+        };
+
+        match (self.shell, labels) {
+            (Shell::Batch, Some(labels)) if keyword == "break" => Ok(format!("goto :{}\n", labels.end)),
+            (Shell::Batch, Some(labels)) => Ok(format!("goto :{}\n", labels.next)),
+            (Shell::Batch, None) => unreachable!("Batch loops that break or continue are given labels"),
+            (Shell::Bash, _) => Ok(format!("{}\n", keyword)),
+        }
+    }
+
+    // Evaluates a bound of a range once, before the loop, so changes made in the body don't move it.
+    // Returns the text the loop header refers to it by
+    fn range_bound(&mut self, bound: &Expr, name: &str, output: &mut String) -> Result<String, Diagnostic> {
+        if let ExprKind::Int(n) = bound.kind {
+            return Ok(n.to_string());
+        }
+
+        let (prelude, bound) = self.hoist_calls(bound)?;
+        output.push_str(&prelude);
+
+        let name = name.to_string();
+        let int = "int".to_string();
+        let typed_statement = Stmt::new(StmtKind::Let {
+            variable_type: int.clone(),
+            name: name.clone(),
+            value: bound.clone(),
+        }, bound.span);
+        output.push_str(&self.compile_let_stmt(&name, &bound, &int, &typed_statement)?);
+
+        match self.shell {
+            Shell::Batch => Ok(format!("!{}!", name)),
+            Shell::Bash => Ok(format!("${{{}}}", name)),
+        }
+    }
+
+    fn compile_function(&mut self, name: &str, parameters: &[Param], return_type: Option<&String>, body: &[Stmt], parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        if let Some(return_type) = return_type {
+            check_variable_type("return", return_type, parent_statement.span)?;
+        }
+
+        for param in parameters {
+            match &param.param_type {
+                Some(param_type) => {
+                    check_variable_type("parameter", param_type, param.span)?;
+                    self.variables.insert(param.name.clone(), param_type.clone());
+                }
+                None => {
+                    self.variables.remove(&param.name);
+                }
+            }
+        }
+
+        // Parameters and the variables declared in the body belong to the function,
+        // anything else it assigns is the script's
+        let mut declared: Vec<String> = parameters.iter().map(|param| param.name.clone()).collect();
+        let mut assigned = Vec::new();
+        self.collect_function_variables(body, &mut declared, &mut assigned);
+        assigned.retain(|name| !declared.contains(name));
+
         let mut output = String::new();
+        let enclosing = self.current_function.replace(name.to_string());
+        let enclosing_exports = std::mem::replace(&mut self.function_exports, assigned);
+        // A loop around the definition doesn't extend into the function's body
+        let enclosing_loops = std::mem::take(&mut self.loops);
 
         match self.shell {
             Shell::Batch => {
                 output.push_str(&format!(":{}\n", name));
-                if let Some(arguments) = args {
-                    for (index, arg) in arguments.iter().enumerate() {
-                        let arg_str = match arg {
-                            Expr::Identifier(id) => id.clone(),
-                            _ => return Err(RosellaError::CompilerError("Function arguments must be identifiers".to_string())),
-                        };
-                        output.push_str(&indent(format!("set {}=%{}\n", arg_str, index + 1)));
-                    }
+                output.push_str(&indent("setlocal\n"));
+                // '%~N' drops the quotes that string arguments are passed in
+                for (index, param) in parameters.iter().enumerate() {
+                    output.push_str(&indent(format!("set \"{}=%~{}\"\n", param.name, index + 1)));
                 }
                 for stmt in body {
                     output.push_str(&indent(self.compile_statement(stmt)?));
                 }
-                output.push_str(&indent("goto :eof\n"));
+                if !matches!(body.last(), Some(Stmt { kind: StmtKind::Return(_), .. })) {
+                    output.push_str(&indent(self.batch_function_exit(false)));
+                }
             } 
             Shell::Bash => {
                 output.push_str(&format!("{}() {{\n", name));
-                if let Some(arguments) = args {
-                    for (index, arg) in arguments.iter().enumerate() {
-                        let arg_str = match arg {
-                            Expr::Identifier(id) => id.clone(),
-                            _ => return Err(RosellaError::CompilerError("Function arguments must be identifiers".to_string())),
-                        };
-                        output.push_str(&indent(format!("local {}=${}\n", arg_str, index + 1)));
-                    }
+                for (index, param) in parameters.iter().enumerate() {
+                    output.push_str(&indent(format!("local {}=${}\n", param.name, index + 1)));
+                }
+                if declared.len() > parameters.len() {
+                    output.push_str(&indent(format!("local {}\n", declared[parameters.len()..].join(" "))));
                 }
                 for stmt in body {
                     output.push_str(&indent(self.compile_statement(stmt)?));
@@ -220,21 +734,203 @@ impl Compiler {
             }
         }
 
+        self.current_function = enclosing;
+        self.function_exports = enclosing_exports;
+        self.loops = enclosing_loops;
+        Ok(output)
+    }
+
+    // Gathers the variables a function body declares with 'let' or counts with 'for', and the ones it assigns.
+    // Functions defined inside it keep their own
+    fn collect_function_variables(&self, body: &[Stmt], declared: &mut Vec<String>, assigned: &mut Vec<String>) {
+        for stmt in body {
+            match &stmt.kind {
+                StmtKind::Let { name, .. } if !declared.contains(name) => declared.push(name.clone()),
+                StmtKind::Assign { name, .. } if !assigned.contains(name) => assigned.push(name.clone()),
+                StmtKind::For { variable, body, .. } => {
+                    if !declared.contains(variable) {
+                        declared.push(variable.clone());
+                    }
+                    self.collect_function_variables(body, declared, assigned);
+                }
+                StmtKind::If { then_branch, else_branch, .. } => {
+                    self.collect_function_variables(then_branch, declared, assigned);
+                    if let Some(else_branch) = else_branch {
+                        self.collect_function_variables(else_branch, declared, assigned);
+                    }
+                }
+                StmtKind::With { os, body } if *os == self.os => self.collect_function_variables(body, declared, assigned),
+                StmtKind::While { body, .. } => self.collect_function_variables(body, declared, assigned),
+                _ => {}
+            }
+        }
+    }
+
+    // Leaves a Batch function. endlocal discards everything set since the function's setlocal, so
+    // __ret and the script variables the function assigned are carried past it in for variables,
+    // which are expanded before endlocal runs. The leading '=' stops an empty value being skipped
+    fn batch_function_exit(&self, returns_value: bool) -> String {
+        let carried: Vec<&str> = returns_value.then_some("__ret").into_iter()
+            .chain(self.function_exports.iter().map(String::as_str))
+            .collect();
+
+        // Without anything to carry, leaving the call ends the setlocal by itself
+        if carried.is_empty() {
+            return "goto :eof\n".to_string();
+        }
+
+        let mut loops = String::new();
+        let mut sets = String::new();
+        let letters = ('a'..='z').filter(|ch| !self.loop_letters.contains(ch));
+        for (name, letter) in carried.iter().zip(letters) {
+            loops.push_str(&format!("for /f \"delims=\" %%{} in (\"=!{}!\") do ", letter, name));
+            sets.push_str(&format!(" & set \"{}%%{}\"", name, letter));
+        }
+
+        format!("{}endlocal{}\ngoto :eof\n", loops, sets)
+    }
+
+    // Leaves the function, handing the value back to the caller in __ret
+    fn compile_return_stmt(&mut self, value: Option<&Expr>, parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        let Some(function) = self.current_function.clone() else {
+            return Err(Diagnostic::error(codes::RETURN_OUTSIDE_FUNCTION, "'return' can only be used inside a function", parent_statement.span)
+                .with_help("use exit() to end the script early"));
+        };
+
+        let declared = self.functions.get(&function).and_then(|signature| signature.return_type.clone());
+        let mut output = String::new();
+
+        if let (Some(declared), None) = (&declared, value) {
+            return Err(Diagnostic::error(codes::RETURN_TYPE_MISMATCH, format!("'{}' returns {}, but this 'return' has no value", function, declared), parent_statement.span)
+                .with_help(format!("return a value of type {}", declared)));
+        }
+
+        if let Some(value) = value {
+            let (prelude, value) = self.hoist_calls(value)?;
+            output.push_str(&prelude);
+
+            let return_type = match (declared, self.infer_type(&value), &value.kind) {
+                (Some(declared), Some(found), _) if !is_assignable(&declared, &found) => {
+                    return Err(Diagnostic::error(codes::RETURN_TYPE_MISMATCH, format!("'{}' returns {}, but this value is {}", function, declared, found), value.span)
+                        .with_help(format!("return a value of type {}, or change the return type of '{}'", declared, function)));
+                }
+                (Some(declared), _, _) => declared,
+                (None, Some(return_type), _) => {
+                    self.return_types.insert(function, return_type.clone());
+                    return_type
+                }
+                // Untyped parameters are handed back as they are
+                (None, None, ExprKind::Identifier(_)) => "str".to_string(),
+                (None, None, _) => return Err(Diagnostic::error(codes::MISSING_CONDITION_TYPE, format!("Cannot tell the type of the value returned from '{}'", function), value.span)
+                    .with_help(format!("declare the return type, e.g. fn {}(...) -> int", function))),
+            };
+
+            // A returned call has already left its result in __ret
+            if !matches!(&value.kind, ExprKind::Identifier(id) if id == "__ret") {
+                let name = "__ret".to_string();
+                let typed_statement = Stmt::new(StmtKind::Let {
+                    variable_type: return_type.clone(),
+                    name: name.clone(),
+                    value: value.clone(),
+                }, parent_statement.span);
+
+                output.push_str(&self.compile_let_stmt(&name, &value, &return_type, &typed_statement)?);
+            }
+        }
+
+        match self.shell {
+            Shell::Batch => output.push_str(&self.batch_function_exit(value.is_some())),
+            Shell::Bash => output.push_str("return\n"),
+        }
+
         Ok(output)
     }
 
-    fn compile_function_call(&self, name: &String, args: &Vec<Expr>) -> Result<String, RosellaError> {
+    // Moves calls to user functions out of an expression so they run before the statement using it.
+    // Each call is replaced by the variable holding its result
+    fn hoist_calls(&mut self, expr: &Expr) -> Result<(String, Expr), Diagnostic> {
+        let mut remaining = count_user_calls(expr);
+        let mut prelude = String::new();
+
+        let expr = self.hoist_expr(expr, &mut remaining, &mut prelude)?;
+        Ok((prelude, expr))
+    }
+
+    fn hoist_expr(&mut self, expr: &Expr, remaining: &mut usize, prelude: &mut String) -> Result<Expr, Diagnostic> {
+        let kind = match &expr.kind {
+            // Hoisted calls always run, where the right of '&&' and '||' is only evaluated when the left doesn't decide
+            ExprKind::Binary { operator, right, .. } if operator.is_logical() && count_user_calls(right) > 0 => {
+                let symbol = if *operator == BinaryOp::And { "&&" } else { "||" };
+                return Err(Diagnostic::error(codes::CALL_IN_SHORT_CIRCUIT, format!("Functions cannot be called on the right of '{}'", symbol), right.span)
+                    .with_help("call the function in its own 'let' before the condition, or move it to the left"));
+            }
+            ExprKind::Binary { left, operator, right } => ExprKind::Binary {
+                left: Box::new(self.hoist_expr(left, remaining, prelude)?),
+                operator: *operator,
+                right: Box::new(self.hoist_expr(right, remaining, prelude)?),
+            },
+            ExprKind::Unary { operator, operand } => ExprKind::Unary {
+                operator: *operator,
+                operand: Box::new(self.hoist_expr(operand, remaining, prelude)?),
+            },
+            ExprKind::Index { target, index } => ExprKind::Index {
+                target: Box::new(self.hoist_expr(target, remaining, prelude)?),
+                index: Box::new(self.hoist_expr(index, remaining, prelude)?),
+            },
+            ExprKind::Interpolated(parts) => {
+                let mut hoisted = Vec::new();
+                for part in parts {
+                    match part {
+                        InterpolatedPart::Expr(expr) => hoisted.push(InterpolatedPart::Expr(self.hoist_expr(expr, remaining, prelude)?)),
+                        InterpolatedPart::Literal(_) => hoisted.push(part.clone()),
+                    }
+                }
+                ExprKind::Interpolated(hoisted)
+            }
+            ExprKind::Call { name, args } => {
+                let args = args.iter().map(|arg| self.hoist_expr(arg, remaining, prelude)).collect::<Result<Vec<_>, _>>()?;
+                if STD_FUNCTIONS.contains(&name.as_str()) {
+                    return Ok(Expr::new(ExprKind::Call { name: name.clone(), args }, expr.span));
+                }
+
+                prelude.push_str(&self.compile_function_call(name, &args, expr.span)?);
+                *remaining -= 1;
+
+                // The last result can be read straight from __ret, earlier ones would be overwritten by the calls after them
+                let result = if *remaining == 0 {
+                    "__ret".to_string()
+                }
+                else {
+                    let result = format!("__ret_{}", self.next_unique_index());
+                    match self.shell {
+                        Shell::Batch => prelude.push_str(&format!("set \"{}=!__ret!\"\n", result)),
+                        Shell::Bash => prelude.push_str(&format!("{}=${{__ret}}\n", result)),
+                    }
+                    result
+                };
+
+                match self.return_types.get(name).cloned() {
+                    Some(return_type) => self.variables.insert(result.clone(), return_type),
+                    None => self.variables.remove(&result),
+                };
+
+                ExprKind::Identifier(result)
+            }
+            _ => return Ok(expr.clone()),
+        };
+
+        Ok(Expr::new(kind, expr.span))
+    }
+
+    fn compile_function_call(&self, name: &String, args: &[Expr], span: Span) -> Result<String, Diagnostic> {
         let mut output = String::new();
 
-        let allowed_std_functions = [
-            "cd", "print", "echo", "make_dir", "mkdir", 
-            "remove_dir", "rmdir", "remove", "del",
-            "path", "copy", "cp", "move", "mv", 
-            "write_file", "append_file", "get_cwd", "read",
-            "exit", "exists", "not_exists", "concat"
-        ];
-        if allowed_std_functions.contains(&name.as_str()) {
-            return self.compile_std_function_call(name, args);
+        if STD_FUNCTIONS.contains(&name.as_str()) {
+            return self.compile_std_function_call(name, args, span);
+        }
+
+        if let Some(signature) = self.functions.get(name) {
+            self.check_call(name, signature, args, span)?;
         }
 
         match self.shell {
@@ -242,11 +938,14 @@ impl Compiler {
                 output.push_str(format!("call :{} ", name).as_str());
                 if !args.is_empty() {
                     for arg in args {
-                        match arg {
-                            Expr::Identifier(id) => output.push_str(format!("!{}! ", id).as_str()),
-                            Expr::String(s) => output.push_str(format!("\"{}\" ", s).as_str()),
-                            Expr::Number(n) => output.push_str(format!("{} ", n).as_str()),
-                            _ => return Err(RosellaError::CompilerError(format!("Unsupported argument type in function call: {:?}", arg))),
+                        match &arg.kind {
+                            ExprKind::Identifier(id) => output.push_str(format!("!{}! ", id).as_str()),
+                            ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => output.push_str(format!("\"{}\" ", self.compile_string(arg)?).as_str()),
+                            ExprKind::Int(n) => output.push_str(format!("{} ", n).as_str()),
+                            ExprKind::Float(n) => output.push_str(format!("{} ", format_float(*n)).as_str()),
+                            ExprKind::Boolean(value) => output.push_str(format!("{} ", u8::from(*value)).as_str()),
+                            _ => return Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot pass {} to a function", arg.kind.description()), arg.span)
+                                .with_help("store the value in a variable first")),
                         }
                     }
                 }
@@ -256,11 +955,14 @@ impl Compiler {
 
                 if !args.is_empty() {
                     for arg in args {
-                        match arg {
-                            Expr::Identifier(id) => output.push_str(format!("\"${{{}}}\" ", id).as_str()),
-                            Expr::String(s) => output.push_str(format!("\"{}\" ", s).as_str()),
-                            Expr::Number(n) => output.push_str(format!("{} ", n).as_str()),
-                            _ => return Err(RosellaError::CompilerError(format!("Unsupported argument type in function call: {:?}", arg))),
+                        match &arg.kind {
+                            ExprKind::Identifier(id) => output.push_str(format!("\"${{{}}}\" ", id).as_str()),
+                            ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => output.push_str(format!("\"{}\" ", self.compile_string(arg)?).as_str()),
+                            ExprKind::Int(n) => output.push_str(format!("{} ", n).as_str()),
+                            ExprKind::Float(n) => output.push_str(format!("{} ", format_float(*n)).as_str()),
+                            ExprKind::Boolean(value) => output.push_str(format!("{} ", u8::from(*value)).as_str()),
+                            _ => return Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot pass {} to a function", arg.kind.description()), arg.span)
+                                .with_help("store the value in a variable first")),
                         }
                     }
                 }
@@ -272,13 +974,38 @@ impl Compiler {
         Ok(output)
     }
 
-    fn compile_std_function_call(&self, name: &String, args: &Vec<Expr>) -> Result<String, RosellaError> {
+    // Catches calls that don't match the function's signature, which Batch would otherwise run with missing arguments
+    fn check_call(&self, name: &str, signature: &Signature, args: &[Expr], span: Span) -> Result<(), Diagnostic> {
+        let expected = signature.parameters.len();
+        if args.len() != expected {
+            let parameters = signature.parameters.iter().map(|param| match &param.param_type {
+                Some(param_type) => format!("{} {}", param_type, param.name),
+                None => param.name.clone(),
+            });
+            return Err(Diagnostic::error(codes::ARGUMENT_COUNT, format!(
+                "{}() takes {} argument{}, but {} {} given",
+                name, expected, if expected == 1 { "" } else { "s" }, args.len(), if args.len() == 1 { "was" } else { "were" }), span)
+                .with_note(format!("the signature is {}({})", name, parameters.collect::<Vec<_>>().join(", "))));
+        }
+
+        for (param, arg) in signature.parameters.iter().zip(args) {
+            if let (Some(param_type), Some(found)) = (&param.param_type, self.infer_type(arg))
+                && !is_assignable(param_type, &found) {
+                return Err(Diagnostic::error(codes::INVALID_ARGUMENT_TYPE, format!("Expected {} for parameter '{}' of {}(), found {}", param_type, param.name, name, found), arg.span)
+                    .with_label(param.span, "parameter declared here"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile_std_function_call(&self, name: &String, args: &[Expr], span: Span) -> Result<String, Diagnostic> {
         let mut output = String::new();
 
         match name.as_str() {
             "cd" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("cd requires a directory argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "cd requires a directory argument", span));
                 }
 
                 output.push_str("cd ");
@@ -287,39 +1014,39 @@ impl Compiler {
             }
             "print" | "echo" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("print/echo requires at least one argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "print/echo requires at least one argument", span));
                 }
 
                 match self.shell {
+                    Shell::Bash if matches!(args, [Expr { kind: ExprKind::RawString(_), .. }]) => {
+                        if let ExprKind::RawString(text) = &args[0].kind {
+                            output.push_str(&self.compile_heredoc(text, ""));
+                        }
+                    }
                     Shell::Bash => {
                         output.push_str("echo \"");
                         for arg in args {
-                            match arg {
-                                Expr::String(s) => output.push_str(s),
-                                Expr::Identifier(id) => output.push_str(format!("${{{}}}", id).as_str()),
-                                Expr::Number(n) => output.push_str(n.to_string().as_str()),
-                                _ => return Err(RosellaError::CompilerError(format!("Unsupported argument type in print/echo: {:?}", arg))),
+                            match &arg.kind {
+                                ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => output.push_str(&self.compile_string(arg)?),
+                                ExprKind::Identifier(id) => output.push_str(format!("${{{}}}", id).as_str()),
+                                ExprKind::Int(n) => output.push_str(n.to_string().as_str()),
+                                ExprKind::Float(n) => output.push_str(format_float(*n).as_str()),
+                                _ => return Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot print {}", arg.kind.description()), arg.span)),
                             }
                         }
                         output.push_str("\"\n");
                     }
                     Shell::Batch => {
-                        output.push_str("echo ");
-                        for arg in args {
-                            match arg {
-                                Expr::String(s) => output.push_str(s),
-                                Expr::Identifier(id) => output.push_str(format!("!{}!", id).as_str()),
-                                Expr::Number(n) => output.push_str(n.to_string().as_str()),
-                                _ => return Err(RosellaError::CompilerError(format!("Unsupported argument type in print/echo: {:?}", arg))),
-                            }
-                        }
-                        output.push('\n');
+                        let lines = self.batch_echo_lines(args, |arg| {
+                            Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot print {}", arg.kind.description()), arg.span)
+                        })?;
+                        output.push_str(&echo_lines(lines));
                     }
                 }
             }
             "make_dir" | "mkdir" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("make_dir requires at least one argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "make_dir requires at least one argument", span));
                 }
 
                 match self.shell {
@@ -331,7 +1058,7 @@ impl Compiler {
             }
             "remove_dir" | "rmdir" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("remove_dir requires at least one argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "remove_dir requires at least one argument", span));
                 }
 
                 match self.shell {
@@ -343,7 +1070,7 @@ impl Compiler {
             }
             "remove" | "del" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("remove requires at least one argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "remove requires at least one argument", span));
                 }
 
                 match self.shell {
@@ -356,14 +1083,14 @@ impl Compiler {
             }
             "path" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("path requires at least one argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "path requires at least one argument", span));
                 }
 
                 output.push_str(self.format_path(args)?.as_str());
             }
             "copy" | "cp" => {
                 if args.len() != 2 {
-                    return Err(RosellaError::CompilerError("copy/cp requires exactly two arguments".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "copy/cp requires exactly two arguments", span));
                 }
 
                 match self.shell {
@@ -372,11 +1099,11 @@ impl Compiler {
                 }
 
                 for arg in args {
-                    let arg_str = match arg {
-                        Expr::Call { name, args } if name == "path" => {
+                    let arg_str = match &arg.kind {
+                        ExprKind::Call { name, args } if name == "path" => {
                             self.format_path(args)?
                         }
-                        _ => return Err(RosellaError::CompilerError(format!("copy/cp requires path() as argument, not: {:?}", arg))),
+                        _ => return Err(Diagnostic::error(codes::PATH_ARGUMENT_REQUIRED, format!("copy/cp requires path() as argument, found {}", arg.kind.description()), arg.span)),
                     };
 
                     output.push_str(arg_str.as_str());
@@ -386,7 +1113,7 @@ impl Compiler {
             }
             "move" | "mv" => {
                 if args.len() != 2 {
-                    return Err(RosellaError::CompilerError("move/mv requires exactly two arguments".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "move/mv requires exactly two arguments", span));
                 }
 
                 match self.shell {
@@ -395,11 +1122,11 @@ impl Compiler {
                 }
 
                 for arg in args {
-                    let arg_str = match arg {
-                        Expr::Call { name, args } if name == "path" => {
+                    let arg_str = match &arg.kind {
+                        ExprKind::Call { name, args } if name == "path" => {
                             self.format_path(args)?
                         }
-                        _ => return Err(RosellaError::CompilerError(format!("move/mv requires path() as argument, not: {:?}", arg))),
+                        _ => return Err(Diagnostic::error(codes::PATH_ARGUMENT_REQUIRED, format!("move/mv requires path() as argument, found {}", arg.kind.description()), arg.span)),
                     };
 
                     output.push_str(arg_str.as_str());
@@ -409,30 +1136,55 @@ impl Compiler {
             }
             "write_file" | "append_file" => {
                 if args.len() != 2 {
-                    return Err(RosellaError::CompilerError(format!("{} requires at exactly two arguments: a path() and concat()", name)));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, format!("{} requires exactly two arguments: a path() and concat()", name), span));
                 }
 
-                let path = match &args[0] {
-                    Expr::Call { name, args } if name == "path" => {
+                let path = match &args[0].kind {
+                    ExprKind::Call { name, args } if name == "path" => {
                         self.format_path(args)?
                     }
-                    _ => return Err(RosellaError::CompilerError(format!("{} requires path() as argument, not: {:?}", name, args[0]))),
+                    _ => return Err(Diagnostic::error(codes::PATH_ARGUMENT_REQUIRED, format!("{} requires path() as argument, found {}", name, args[0].kind.description()), args[0].span)),
                 };
 
-                let content = match &args[1] {
-                    Expr::Call { name, args } if name == "concat" => {
-                        self.compile_std_function_call(name, args)?
+                let operator = if name == "write_file" { ">" } else { ">>" };
+
+                // Raw strings are written out line by line instead of through a single echo
+                if let ExprKind::RawString(text) = &args[1].kind {
+                    output.push_str(&self.compile_heredoc(text, &format!(" {} {}", operator, path)));
+                    return Ok(output);
+                }
+
+                // Batch keeps the quotes of an echoed string, so the text is echoed bare, as print does
+                if self.shell == Shell::Batch {
+                    let parts = match &args[1].kind {
+                        ExprKind::Call { name, args } if name == "concat" && args.is_empty() => {
+                            return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "concat requires at least one argument", span));
+                        }
+                        ExprKind::Call { name, args } if name == "concat" => args.as_slice(),
+                        ExprKind::String(_) | ExprKind::Interpolated(_) => &args[1..],
+                        _ => return Err(Diagnostic::error(codes::CONCAT_ARGUMENT_REQUIRED, format!("{} requires a string or concat() as argument, found {}", name, args[1].kind.description()), args[1].span)),
+                    };
+
+                    let lines = self.batch_echo_lines(parts, |arg| {
+                        Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("concat requires string or identifier arguments, found {}", arg.kind.description()), arg.span)
+                    })?;
+                    output.push_str(format!("(\n{}) {} {}\n", indent(echo_lines(lines)), operator, path).as_str());
+                    return Ok(output);
+                }
+
+                let content = match &args[1].kind {
+                    ExprKind::Call { name, args } if name == "concat" => {
+                        self.compile_std_function_call(name, args, span)?
                     }
-                    _ => return Err(RosellaError::CompilerError(format!("{} requires concat() as argument, not: {:?}", name, args[1]))),
+                    ExprKind::String(_) | ExprKind::Interpolated(_) => format!("\"{}\"", self.compile_string(&args[1])?),
+                    _ => return Err(Diagnostic::error(codes::CONCAT_ARGUMENT_REQUIRED, format!("{} requires a string or concat() as argument, found {}", name, args[1].kind.description()), args[1].span)),
                 };
 
-                let operator = if name == "write_file" { ">" } else { ">>" };
-
                 output.push_str(format!("echo {} {} {}\n", content, operator, path).as_str());
             }
             "get_cwd" => {
-                if args.len() > 0 {
-                    return Err(RosellaError::CompilerError("get_cwd() requires has no arguments.".to_string()))
+                if !args.is_empty() {
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "get_cwd() takes no arguments", span))
                 }
 
                 match self.shell {
@@ -442,17 +1194,17 @@ impl Compiler {
             }
             "read" => {
                 if args.len() != 2 {
-                    return Err(RosellaError::CompilerError("read requires exactly two arguments".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "read requires exactly two arguments", span));
                 }
 
-                let prompt = match &args[0] {
-                    Expr::String(s) => s,
-                    _ => return Err(RosellaError::CompilerError("First argument of read must be a string".to_string())),
+                let prompt = match &args[0].kind {
+                    ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => self.compile_string(&args[0])?,
+                    _ => return Err(Diagnostic::error(codes::INVALID_ARGUMENT_TYPE, "First argument of read must be a string", args[0].span)),
                 };
 
-                let variable = match &args[1] {
-                    Expr::Identifier(id) => id,
-                    _ => return Err(RosellaError::CompilerError("Second argument of read must be an identifier".to_string())),
+                let variable = match &args[1].kind {
+                    ExprKind::Identifier(id) => id,
+                    _ => return Err(Diagnostic::error(codes::INVALID_ARGUMENT_TYPE, "Second argument of read must be an identifier", args[1].span)),
                 };
 
                 match self.shell {
@@ -473,12 +1225,12 @@ impl Compiler {
             }
             "exit" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("exit requires an exit code argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "exit requires an exit code argument", span));
                 }
 
-                let exit_code = match &args[0] {
-                    Expr::Number(n) => n.to_string(),
-                    _ => return Err(RosellaError::CompilerError("First argument of exit must be a number".to_string())),
+                let exit_code = match &args[0].kind {
+                    ExprKind::Int(n) => n.to_string(),
+                    _ => return Err(Diagnostic::error(codes::INVALID_ARGUMENT_TYPE, "First argument of exit must be a whole number", args[0].span)),
                 };
 
                 match self.shell {
@@ -488,7 +1240,7 @@ impl Compiler {
             }
             "exists" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("exists requires a file path argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "exists requires a file path argument", span));
                 }
 
                 match self.shell {
@@ -500,7 +1252,7 @@ impl Compiler {
             }
             "not_exists" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("not_exists requires a file path argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "not_exists requires a file path argument", span));
                 }
 
                 match self.shell {
@@ -512,23 +1264,24 @@ impl Compiler {
             }
             "concat" => {
                 if args.is_empty() {
-                    return Err(RosellaError::CompilerError("concat requires at least one argument".to_string()));
+                    return Err(Diagnostic::error(codes::ARGUMENT_COUNT, "concat requires at least one argument", span));
                 }
 
                 output.push('"');
                 for arg in args {
-                    let arg_str = match arg {
-                        Expr::Number(n) => Ok(n.to_string()),
-                        Expr::String(s) => Ok(s.clone()),
-                        Expr::Identifier(id) => match self.shell {
+                    let arg_str = match &arg.kind {
+                        ExprKind::Int(n) => Ok(n.to_string()),
+                        ExprKind::Float(n) => Ok(format_float(*n)),
+                        ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => self.compile_string(arg),
+                        ExprKind::Identifier(id) => match self.shell {
                             Shell::Batch => Ok(format!("!{}!", id)),
                             Shell::Bash => Ok(format!("${{{}}}", id)),
                         },
-                        _ => return Err(RosellaError::CompilerError(format!("concat requires string or identifier arguments, not: {:?}", arg))),
+                        _ => return Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("concat requires string or identifier arguments, found {}", arg.kind.description()), arg.span)),
                     };
                     output.push_str(arg_str?.as_str());
                 }
-                output.push_str("\"");
+                output.push('"');
             }
 
             _ => unreachable!("Standard function call compilation not implemented for: {}", name),
@@ -537,21 +1290,22 @@ impl Compiler {
         Ok(output)
     }
 
-    fn compile_raw_instruction(&self, instructions: &Vec<Expr>, parent_statement: &Stmt) -> Result<String, RosellaError> {
+    fn compile_raw_instruction(&self, instructions: &[Expr], parent_statement: &Stmt) -> Result<String, Diagnostic> {
         let mut output = String::new();
 
         for instruction in instructions {
-            match instruction {
-                Expr::String(s) => output.push_str(format!("\"{}\" ", s).as_str()),
-                Expr::Identifier(s) => output.push_str(format!("{} ", s).as_str()),
-                Expr::Binary { left, operator, right } => {
+            match &instruction.kind {
+                ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => output.push_str(format!("\"{}\" ", self.compile_string(instruction)?).as_str()),
+                ExprKind::Identifier(s) => output.push_str(format!("{} ", s).as_str()),
+                ExprKind::Binary { left, operator, right } => {
                     let left_str = self.compile_expr(left, parent_statement)?;
                     let operator_str = self.format_operator(*operator, parent_statement)?;
                     let right_str = self.compile_expr(right, parent_statement)?;
                     output.push_str(format!("{} {} {} ", left_str, operator_str, right_str).as_str());
                 },
-                Expr::Number(n) => output.push_str(format!("{} ", n).as_str()),
-                _ => return Err(RosellaError::CompilerError(format!("Unsupported raw instruction: {:?}", instruction))),
+                ExprKind::Int(n) => output.push_str(format!("{} ", n).as_str()),
+                ExprKind::Float(n) => output.push_str(format!("{} ", format_float(*n)).as_str()),
+                _ => return Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot use {} in a raw instruction", instruction.kind.description()), instruction.span)),
             }
         }
 
@@ -560,52 +1314,382 @@ impl Compiler {
         Ok(output)
     }
 
-    fn compile_expr(&self, expr: &Expr, parent_statement: &Stmt) -> Result<String, RosellaError> {
-        match expr {
-            Expr::Number(n) => Ok(n.to_string()),
-            Expr::String(s) => match self.shell {
-                Shell::Batch => Ok(s.clone()),
-                Shell::Bash => Ok(format!("\"{}\"", s)),
-            }//Ok(format!("\"{}\"", s)),
-            Expr::Identifier(id) => match self.shell {
-                Shell::Batch => Ok(format!("!{}!", id)),
-                Shell::Bash => Ok(format!("${{{}}}", id)),
-            },
-            Expr::Binary { left, operator, right } => {
-                let left_str = self.compile_expr(left, parent_statement)?;
-                let operator_str = self.format_operator(*operator, parent_statement)?;
-                let right_str = self.compile_expr(right, parent_statement)?;
-
+    fn compile_expr(&self, expr: &Expr, parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        match &expr.kind {
+            ExprKind::Int(n) => Ok(n.to_string()),
+            ExprKind::Float(n) => {
                 let condition_type = self.get_condition_type(parent_statement)?;
+                if condition_type != "str" && condition_type != "float" {
+                    return Err(Diagnostic::error(codes::FLOAT_NOT_ALLOWED, format!("Expected {}, found float literal {}", condition_type, format_float(*n)), expr.span)
+                        .with_help("shell arithmetic only supports whole numbers"));
+                }
+
+                Ok(format_float(*n))
+            }
+            ExprKind::Boolean(value) => {
+                let value = u8::from(*value).to_string();
+                if self.is_bool_condition(parent_statement) {
+                    Ok(self.bool_test(&value))
+                }
+                else {
+                    Ok(value)
+                }
+            }
+            ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => match self.shell {
+                Shell::Batch => self.compile_string(expr),
+                Shell::Bash => Ok(format!("\"{}\"", self.compile_string(expr)?)),
+            },
+            ExprKind::Identifier(id) => {
+                if self.variables.get(id).is_some_and(|variable_type| variable_type == "float")
+                    && matches!(self.get_condition_type(parent_statement), Ok(condition_type) if condition_type == "int") {
+                    return Err(Diagnostic::error(codes::FLOAT_NOT_ALLOWED, format!("Expected int, found float variable '{}'", id), expr.span)
+                        .with_help("shell arithmetic only supports whole numbers"));
+                }
+
+                let value = match self.shell {
+                    Shell::Batch => format!("!{}!", id),
+                    Shell::Bash => format!("${{{}}}", id),
+                };
+
+                if self.is_bool_condition(parent_statement) {
+                    Ok(self.bool_test(&value))
+                }
+                else {
+                    Ok(value)
+                }
+            }
+            ExprKind::Binary { left, operator, right } if operator.is_logical() => {
+                self.check_condition_type(parent_statement)?;
+                self.check_logical_operator(expr, parent_statement)?;
+
+                let left_str = self.compile_logical_operand(left, *operator, parent_statement)?;
+                let operator_str = self.format_operator(*operator, parent_statement)?;
+                let right_str = self.compile_logical_operand(right, *operator, parent_statement)?;
+
+                Ok(format!("{} {} {}", left_str, operator_str, right_str))
+            }
+            ExprKind::Unary { operator: UnaryOp::Not, operand } => {
+                self.check_condition_type(parent_statement)?;
+                self.check_logical_operator(expr, parent_statement)?;
+
+                match &operand.kind {
+                    ExprKind::Binary { operator, .. } if operator.is_logical() => Ok(format!("! ( {} )", self.compile_expr(operand, parent_statement)?)),
+                    _ => Ok(format!("! {}", self.compile_expr(operand, parent_statement)?)),
+                }
+            }
+            ExprKind::Unary { operator: UnaryOp::Negate, operand } => {
+                let condition_type = self.check_condition_type(parent_statement)?;
+                if condition_type != "int" {
+                    return Err(Diagnostic::error(codes::UNSUPPORTED_OPERATOR, format!("Cannot negate a value of type {}", condition_type), expr.span)
+                        .with_help("negation is only supported for 'int'"));
+                }
+
+                // The parentheses stop a negative operand from turning into '--'
+                let operand_str = self.compile_expr(operand, parent_statement)?;
+                match self.shell {
+                    Shell::Bash => Ok(format!("$((-({})))", operand_str)),
+                    Shell::Batch => Ok(format!("-({})", operand_str)),
+                }
+            }
+            ExprKind::Binary { left, operator, right } => {
+                let condition_type = self.check_condition_type(parent_statement)?;
+                if condition_type == "bool" {
+                    return self.compile_bool_comparison(expr, left, *operator, right, parent_statement);
+                }
+                if condition_type == "float" {
+                    return self.compile_float_comparison(expr, left, *operator, right);
+                }
+
+                let left_str = self.compile_expr(left, parent_statement)?;
+                let operator_str = self.format_operator(*operator, parent_statement)?;
+                let right_str = self.compile_expr(right, parent_statement)?;
 
                 match (self.shell, condition_type.as_str()) {
                     (Shell::Bash, "int") => {
                         match operator {
-                            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
-                                return Ok(format!("$(({} {} {}))", left_str, operator_str, right_str));
+                            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => {
+                                Ok(format!("$(({} {} {}))", left_str, operator_str, right_str))
                             },
-                            _ => return Ok(format!("{} {} {}", left_str, operator_str, right_str))
+                            _ => Ok(format!("{} {} {}", left_str, operator_str, right_str))
                         }
                     }
                     (Shell::Batch, "int") => {
-                        return Ok(format!("{} {} {}", left_str, operator_str, right_str));
+                        Ok(format!("{} {} {}", left_str, operator_str, right_str))
                     }
                     (Shell::Bash, "str") => {
-                        return Ok(format!("{} {} {}", left_str, operator_str, right_str)); 
+                        Ok(format!("{} {} {}", left_str, operator_str, right_str))
                     }
                     (Shell::Batch, "str") => {
-                        return Ok(format!("\"{}\" {} \"{}\"", left_str, operator_str, right_str));
+                        Ok(format!("\"{}\" {} \"{}\"", left_str, operator_str, right_str))
                     }
-                    _ => return Err(RosellaError::CompilerError(format!("Unsupported condition type: {}", condition_type))),
+                    _ => unreachable!("condition type is checked above"),
                 }
             },
-            Expr::Call { name, args } => {
-                self.compile_function_call(name, args)
+            ExprKind::Call { name, args } => {
+                self.compile_function_call(name, args, expr.span)
+            }
+            ExprKind::Index { .. } => Err(Diagnostic::error(codes::INDEX_NOT_SUPPORTED, "Indexing is not supported", expr.span)
+                .with_help("Rosella has no arrays yet; store each value in its own variable")),
+        }
+    }
+
+    fn is_bool_condition(&self, statement: &Stmt) -> bool {
+        matches!(self.get_condition_type(statement), Ok(condition_type) if condition_type == "bool")
+    }
+
+    // Tests whether a stored bool is true
+    fn bool_test(&self, value: &str) -> String {
+        match self.shell {
+            Shell::Batch => format!("{} EQU 1", value),
+            Shell::Bash => format!("{} -eq 1", value),
+        }
+    }
+
+    // A comparison inside a bool condition takes its type from the values being compared
+    fn compile_bool_comparison(&self, expr: &Expr, left: &Expr, operator: BinaryOp, right: &Expr, parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        if !operator.is_comparison() {
+            return Err(Diagnostic::error(codes::UNSUPPORTED_OPERATOR, format!("Operator {:?} does not produce a bool", operator), expr.span)
+                .with_help("only comparisons, '&&', '||' and '!' can be used in a bool condition"));
+        }
+
+        let comparison_type = match self.infer_operands_type(left, right) {
+            Some(comparison_type) => comparison_type,
+            None => return Err(Diagnostic::error(codes::MISSING_CONDITION_TYPE, "Cannot tell whether this compares numbers or text", expr.span)
+                .with_help("declare the variables with 'let', or compare inside 'if int(...)' or 'if str(...)'")),
+        };
+
+        // Bools are compared as the 1 or 0 they are stored as
+        let comparison_type = match comparison_type.as_str() {
+            "bool" => {
+                for operand in [left, right] {
+                    if !matches!(operand.kind, ExprKind::Boolean(_) | ExprKind::Identifier(_)) {
+                        return Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot compare {} as a bool", operand.kind.description()), operand.span)
+                            .with_help("store the condition in a 'let bool' first"));
+                    }
+                }
+                "int".to_string()
+            }
+            _ => comparison_type,
+        };
+
+        let typed_statement = Stmt::new(StmtKind::If {
+            condition_type: comparison_type,
+            condition: expr.clone(),
+            then_branch: Vec::new(),
+            else_branch: None,
+        }, parent_statement.span);
+
+        self.compile_expr(expr, &typed_statement)
+    }
+
+    // The type of an expression, where it can be told from the expression itself
+    fn infer_type(&self, expr: &Expr) -> Option<String> {
+        match &expr.kind {
+            ExprKind::Int(_) | ExprKind::Unary { operator: UnaryOp::Negate, .. } => Some("int".to_string()),
+            ExprKind::Float(_) => Some("float".to_string()),
+            ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => Some("str".to_string()),
+            ExprKind::Boolean(_) | ExprKind::Unary { operator: UnaryOp::Not, .. } => Some("bool".to_string()),
+            ExprKind::Identifier(name) => self.variables.get(name).cloned(),
+            ExprKind::Binary { operator, .. } if operator.is_logical() || operator.is_comparison() => Some("bool".to_string()),
+            ExprKind::Binary { left, right, .. } => self.infer_operands_type(left, right),
+            ExprKind::Call { name, .. } if name == "exists" || name == "not_exists" => Some("bool".to_string()),
+            ExprKind::Call { name, .. } => self.return_types.get(name).cloned(),
+            ExprKind::Index { .. } => None,
+        }
+    }
+
+    // Mixing an int with a float gives a float
+    fn infer_operands_type(&self, left: &Expr, right: &Expr) -> Option<String> {
+        let left_type = self.infer_type(left);
+        let right_type = self.infer_type(right);
+
+        if left_type.as_deref() == Some("float") || right_type.as_deref() == Some("float") {
+            Some("float".to_string())
+        }
+        else {
+            left_type.or(right_type)
+        }
+    }
+
+    // The type a comparison is made in, which for bool conditions comes from the values being compared
+    fn comparison_type(&self, left: &Expr, right: &Expr, parent_statement: &Stmt) -> Option<String> {
+        match self.get_condition_type(parent_statement) {
+            Ok(condition_type) if condition_type == "bool" => self.infer_operands_type(left, right),
+            Ok(condition_type) => Some(condition_type),
+            Err(_) => None,
+        }
+    }
+
+    // Builds float arithmetic in the syntax of awk on Bash and PowerShell on Batch
+    fn compile_float_expr(&self, expr: &Expr) -> Result<String, Diagnostic> {
+        match &expr.kind {
+            ExprKind::Int(n) => Ok(n.to_string()),
+            ExprKind::Float(n) => Ok(format_float(*n)),
+            ExprKind::Identifier(id) => match self.shell {
+                Shell::Batch => Ok(format!("!{}!", id)),
+                Shell::Bash => Ok(format!("${{{}}}", id)),
+            },
+            ExprKind::Unary { operator: UnaryOp::Negate, operand } => Ok(format!("-({})", self.compile_float_expr(operand)?)),
+            ExprKind::Binary { left, operator, right } if !operator.is_logical() && !operator.is_comparison() => {
+                Ok(format!("({} {} {})", self.compile_float_expr(left)?, self.float_operator(*operator), self.compile_float_expr(right)?))
             }
+            _ => Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot use {} in float arithmetic", expr.kind.description()), expr.span)
+                .with_help("float arithmetic works on numbers and variables")),
+        }
+    }
+
+    fn compile_float_comparison(&self, expr: &Expr, left: &Expr, operator: BinaryOp, right: &Expr) -> Result<String, Diagnostic> {
+        if !operator.is_comparison() {
+            return Err(Diagnostic::error(codes::UNSUPPORTED_OPERATOR, format!("Operator {:?} does not produce a condition", operator), expr.span)
+                .with_help("compare the result, e.g. a + b > 1.5"));
+        }
+
+        let comparison = format!("{} {} {}", self.compile_float_expr(left)?, self.float_operator(operator), self.compile_float_expr(right)?);
+        match self.shell {
+            // awk prints 1 when the comparison holds
+            Shell::Bash => Ok(format!("$(awk \"BEGIN {{ print ({}) }}\") -eq 1", comparison)),
+            // Batch float comparisons are evaluated ahead of the test by compile_condition
+            Shell::Batch => Err(Diagnostic::error(codes::UNSUPPORTED_OPERATOR, "Float comparisons can only be used as conditions on Batch", expr.span)
+                .with_help("use the comparison in an 'if', a 'while' or a 'let bool'")),
+        }
+    }
+
+    fn float_operator(&self, operator: BinaryOp) -> &str {
+        match (self.shell, operator) {
+            (_, BinaryOp::Add) => "+",
+            (_, BinaryOp::Subtract) => "-",
+            (_, BinaryOp::Multiply) => "*",
+            (_, BinaryOp::Divide) => "/",
+            (Shell::Bash, BinaryOp::Modulo) => "%",
+            (Shell::Batch, BinaryOp::Modulo) => "%%",
+
+            (Shell::Bash, BinaryOp::Equal) => "==",
+            (Shell::Bash, BinaryOp::NotEqual) => "!=",
+            (Shell::Bash, BinaryOp::LessThan) => "<",
+            (Shell::Bash, BinaryOp::LessThanEq) => "<=",
+            (Shell::Bash, BinaryOp::GreaterThan) => ">",
+            (Shell::Bash, BinaryOp::GreaterThanEq) => ">=",
+
+            (Shell::Batch, BinaryOp::Equal) => "-eq",
+            (Shell::Batch, BinaryOp::NotEqual) => "-ne",
+            (Shell::Batch, BinaryOp::LessThan) => "-lt",
+            (Shell::Batch, BinaryOp::LessThanEq) => "-le",
+            (Shell::Batch, BinaryOp::GreaterThan) => "-gt",
+            (Shell::Batch, BinaryOp::GreaterThanEq) => "-ge",
+
+            (Shell::Bash, BinaryOp::And) => "&&",
+            (Shell::Bash, BinaryOp::Or) => "||",
+            (Shell::Batch, BinaryOp::And) => "-and",
+            (Shell::Batch, BinaryOp::Or) => "-or",
+        }
+    }
+
+    // Groups a nested logical expression that uses the other operator, so the intent stays obvious in the output
+    fn compile_logical_operand(&self, operand: &Expr, operator: BinaryOp, parent_statement: &Stmt) -> Result<String, Diagnostic> {
+        let compiled = self.compile_expr(operand, parent_statement)?;
+
+        match &operand.kind {
+            ExprKind::Binary { operator: inner, .. } if inner.is_logical() && *inner != operator => Ok(format!("( {} )", compiled)),
+            _ => Ok(compiled),
+        }
+    }
+
+    // Logical operators only exist inside Bash's [[ ]]; Batch conditions are lowered by compile_condition
+    fn check_logical_operator(&self, expr: &Expr, parent_statement: &Stmt) -> Result<(), Diagnostic> {
+        let in_condition = match &parent_statement.kind {
+            StmtKind::If { .. } | StmtKind::While { .. } => true,
+            StmtKind::Let { variable_type, .. } => variable_type == "bool",
+            _ => false,
+        };
+
+        if in_condition && self.shell == Shell::Bash {
+            Ok(())
+        }
+        else {
+            Err(Diagnostic::error(codes::LOGICAL_OPERATOR_OUTSIDE_CONDITION, "Logical operators can only combine conditions", expr.span)
+                .with_help("use the expression in an 'if', a 'while' or a 'let bool'"))
         }
     }
 
-    fn format_operator(&self, operator: BinaryOp, statement: &Stmt) -> Result<&str, RosellaError> {
+    // Compiles an 'if' or 'while' condition into the lines that have to run before it and the test itself.
+    // Batch has no boolean operators, so '&&', '||' and '!' are evaluated into flag variables first
+    fn compile_condition(&mut self, condition: &Expr, parent_statement: &Stmt) -> Result<(String, String), Diagnostic> {
+        if self.shell == Shell::Bash {
+            return Ok((String::new(), self.compile_expr(condition, parent_statement)?));
+        }
+
+        match &condition.kind {
+            ExprKind::Unary { operator: UnaryOp::Not, operand } => {
+                self.check_condition_type(parent_statement)?;
+
+                let (prelude, test) = self.compile_condition(operand, parent_statement)?;
+                match test.strip_prefix("not ") {
+                    Some(test) => Ok((prelude, test.to_string())),
+                    None => Ok((prelude, format!("not {}", test))),
+                }
+            }
+            ExprKind::Binary { left, operator, right } if operator.is_logical() => {
+                self.check_condition_type(parent_statement)?;
+
+                let (mut prelude, left_test) = self.compile_condition(left, parent_statement)?;
+                let (right_prelude, right_test) = self.compile_condition(right, parent_statement)?;
+                prelude.push_str(&right_prelude);
+
+                let flag = format!("__cond_{}", self.next_unique_index());
+                prelude.push_str(&format!("set \"{}=0\"\n", flag));
+                match operator {
+                    BinaryOp::And => prelude.push_str(&format!("if {} if {} set \"{}=1\"\n", left_test, right_test, flag)),
+                    _ => {
+                        prelude.push_str(&format!("if {} set \"{}=1\"\n", left_test, flag));
+                        prelude.push_str(&format!("if {} set \"{}=1\"\n", right_test, flag));
+                    }
+                }
+
+                Ok((prelude, format!("\"!{}!\" == \"1\"", flag)))
+            }
+            ExprKind::Binary { left, operator, right }
+                if operator.is_comparison() && self.comparison_type(left, right, parent_statement).as_deref() == Some("float") => {
+                // PowerShell prints 1 when the comparison holds
+                let flag = format!("__float_{}", self.next_unique_index());
+                let comparison = format!("{} {} {}", self.compile_float_expr(left)?, self.float_operator(*operator), self.compile_float_expr(right)?);
+                let prelude = format!("for /f %%v in ('powershell -NoProfile -Command \"[int]({})\"') do set \"{}=%%v\"\n", comparison, flag);
+
+                Ok((prelude, format!("!{}! EQU 1", flag)))
+            }
+            ExprKind::Binary { left, operator, right }
+                if operator.is_comparison() && (is_arithmetic(left) || is_arithmetic(right))
+                    && self.comparison_type(left, right, parent_statement).as_deref() == Some("int") => {
+                // 'if' can't do arithmetic, so a calculated side is worked out with set /a first
+                let typed_statement = Stmt::new(StmtKind::If {
+                    condition_type: "int".to_string(),
+                    condition: condition.clone(),
+                    then_branch: Vec::new(),
+                    else_branch: None,
+                }, parent_statement.span);
+
+                let mut prelude = String::new();
+                let mut operands = Vec::new();
+                for operand in [left, right] {
+                    if is_arithmetic(operand) {
+                        let temporary = format!("__tmp_{}", self.next_unique_index());
+                        prelude.push_str(&format!("set /a {}={}\n", temporary, self.compile_expr(operand, &typed_statement)?));
+                        self.variables.insert(temporary.clone(), "int".to_string());
+                        operands.push(Expr::new(ExprKind::Identifier(temporary), operand.span));
+                    }
+                    else {
+                        operands.push(operand.as_ref().clone());
+                    }
+                }
+
+                let [left, right] = <[Expr; 2]>::try_from(operands).expect("a comparison has two operands");
+                let lowered = Expr::new(ExprKind::Binary { left: Box::new(left), operator: *operator, right: Box::new(right) }, condition.span);
+                Ok((prelude, self.compile_expr(&lowered, parent_statement)?))
+            }
+            _ => Ok((String::new(), self.compile_expr(condition, parent_statement)?)),
+        }
+    }
+
+    fn format_operator(&self, operator: BinaryOp, statement: &Stmt) -> Result<&str, Diagnostic> {
         let condition_type = self.get_condition_type(statement)?;
         
         match (self.shell, condition_type.as_str(), operator) {
@@ -614,6 +1698,13 @@ impl Compiler {
             (_, _, BinaryOp::Multiply) => Ok("*"),
             (_, _, BinaryOp::Divide) => Ok("/"),
 
+            // '%' starts a variable expansion in a batch file unless it is doubled
+            (Shell::Bash, _, BinaryOp::Modulo) => Ok("%"),
+            (Shell::Batch, _, BinaryOp::Modulo) => Ok("%%"),
+
+            (Shell::Bash, _, BinaryOp::And) => Ok("&&"),
+            (Shell::Bash, _, BinaryOp::Or) => Ok("||"),
+
             (Shell::Bash, "str", BinaryOp::Equal) => Ok("=="),
             (Shell::Bash, "str", BinaryOp::NotEqual) => Ok("!="),
             (Shell::Bash, "str", BinaryOp::LessThan) => Ok("<"),
@@ -636,24 +1727,25 @@ impl Compiler {
             (Shell::Batch, "int", BinaryOp::LessThanEq) => Ok("LEQ"),
             (Shell::Batch, "int", BinaryOp::GreaterThanEq) => Ok("GEQ"),
 
-            _ => Err(RosellaError::CompilerError(format!(
-                "Operator: {:?} for {:?} on {:?} is not implemented.",
-                operator, condition_type, self.shell)))
+            _ => Err(Diagnostic::error(codes::UNSUPPORTED_OPERATOR, format!(
+                "Operator {:?} for {} is not supported on {:?}",
+                operator, condition_type, self.shell), statement.span))
         }
     }
 
-    fn format_path(&self, args: &Vec<Expr>) -> Result<String, RosellaError> {
+    fn format_path(&self, args: &[Expr]) -> Result<String, Diagnostic> {
         let mut output = String::from('"');
 
         for arg in args {
-            let arg_str = match arg {
-                Expr::Identifier(id) => match self.shell {
+            let arg_str = match &arg.kind {
+                ExprKind::Identifier(id) => match self.shell {
                     Shell::Batch => format!("!{}!", id),
                     Shell::Bash => format!("${{{}}}", id),
                 }
-                Expr::String(s) => s.clone(),
-                Expr::Number(n) => n.to_string(),
-                _ => return Err(RosellaError::CompilerError(format!("Unsupported argument type: {:?}", arg))),
+                ExprKind::String(_) | ExprKind::RawString(_) | ExprKind::Interpolated(_) => self.compile_string(arg)?,
+                ExprKind::Int(n) => n.to_string(),
+                ExprKind::Float(n) => format_float(*n),
+                _ => return Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot use {} in a path", arg.kind.description()), arg.span)),
             };
             match self.os {
                 OS::Windows => output.push_str(format!("\\{}", arg_str).as_str()),
@@ -665,17 +1757,487 @@ impl Compiler {
         Ok(output)
     }
 
-    fn get_condition_type(&self, statement: &Stmt) -> Result<String, RosellaError> {
-        match statement {
-            Stmt::Let { variable_type, .. } => Ok(variable_type.to_string()),
-            Stmt::If { condition_type, .. } => Ok(condition_type.to_string()),
-            Stmt::While { condition_type, .. } => Ok(condition_type.to_string()),
-            _ => return Err(RosellaError::CompilerError("No condition type found for operator formatting".to_string())),
+    // Outputs a raw string verbatim, through a quoted heredoc on Bash and a block of echo lines on Batch
+    // echo can't print a line break, so the text is split into the lines each echo prints
+    fn batch_echo_lines(&self, args: &[Expr], unsupported: impl Fn(&Expr) -> Diagnostic) -> Result<Vec<String>, Diagnostic> {
+        let mut lines = vec![String::new()];
+
+        for arg in args {
+            match &arg.kind {
+                ExprKind::String(s) | ExprKind::RawString(s) => push_echo_text(&mut lines, s),
+                ExprKind::Interpolated(parts) => {
+                    for part in parts {
+                        match part {
+                            InterpolatedPart::Literal(text) => push_echo_text(&mut lines, text),
+                            InterpolatedPart::Expr(expr) => lines.last_mut().unwrap().push_str(&self.interpolate(expr)?),
+                        }
+                    }
+                }
+                ExprKind::Identifier(id) => lines.last_mut().unwrap().push_str(format!("!{}!", id).as_str()),
+                ExprKind::Int(n) => lines.last_mut().unwrap().push_str(n.to_string().as_str()),
+                ExprKind::Float(n) => lines.last_mut().unwrap().push_str(format_float(*n).as_str()),
+                _ => return Err(unsupported(arg)),
+            }
         }
+
+        Ok(lines)
     }
+
+    fn compile_heredoc(&self, text: &str, redirect: &str) -> String {
+        match self.shell {
+            Shell::Bash if text.is_empty() && !redirect.is_empty() => format!(":{}\n", redirect),
+            Shell::Bash => {
+                // The delimiter must not appear as a line of the text
+                let mut delimiter = String::from("ROSELLA_EOF");
+                let mut index = 1;
+                while text.split('\n').any(|line| line == delimiter) {
+                    delimiter = format!("ROSELLA_EOF_{}", index);
+                    index += 1;
+                }
+
+                format!("cat{} <<'{}'\n{}\n{}\n", redirect, delimiter, text, delimiter)
+            }
+            Shell::Batch => {
+                let mut lines = vec![String::new()];
+                push_echo_text(&mut lines, text);
+
+                if redirect.is_empty() {
+                    echo_lines(lines)
+                }
+                else if text.is_empty() {
+                    format!("type nul{}\n", redirect)
+                }
+                else {
+                    format!("(\n{}){}\n", indent(echo_lines(lines)), redirect)
+                }
+            }
+        }
+    }
+
+    // Compiles a string literal to the text that goes between its double quotes
+    fn compile_string(&self, expr: &Expr) -> Result<String, Diagnostic> {
+        match &expr.kind {
+            ExprKind::String(s) | ExprKind::RawString(s) => self.escape_quoted(s, expr.span),
+            ExprKind::Interpolated(parts) => {
+                let mut output = String::new();
+                for part in parts {
+                    match part {
+                        InterpolatedPart::Literal(text) => output.push_str(&self.escape_quoted(text, expr.span)?),
+                        InterpolatedPart::Expr(expr) => output.push_str(&self.interpolate(expr)?),
+                    }
+                }
+                Ok(output)
+            }
+            _ => unreachable!("compile_string called with {}", expr.kind.description()),
+        }
+    }
+
+    fn interpolate(&self, expr: &Expr) -> Result<String, Diagnostic> {
+        match &expr.kind {
+            ExprKind::Identifier(id) => match self.shell {
+                Shell::Batch => Ok(format!("!{}!", id)),
+                Shell::Bash => Ok(format!("${{{}}}", id)),
+            },
+            ExprKind::Int(n) => Ok(n.to_string()),
+            ExprKind::Float(n) => Ok(format_float(*n)),
+            _ => Err(Diagnostic::error(codes::UNSUPPORTED_ARGUMENT, format!("Cannot interpolate {}", expr.kind.description()), expr.span)
+                .with_help("store the value in a variable first and interpolate that")),
+        }
+    }
+
+    // Escapes a string so it can be placed between double quotes in the target shell
+    fn escape_quoted(&self, string: &str, span: Span) -> Result<String, Diagnostic> {
+        let mut output = String::new();
+
+        match self.shell {
+            Shell::Bash => {
+                for ch in string.chars() {
+                    match ch {
+                        '"' | '\\' | '$' | '`' => {
+                            output.push('\\');
+                            output.push(ch);
+                        }
+                        // Control characters drop out of the double quotes into an ANSI-C quoted segment
+                        '\n' => output.push_str("\"$'\\n'\""),
+                        '\r' => output.push_str("\"$'\\r'\""),
+                        ch if ch.is_control() && ch != '\t' => output.push_str(&format!("\"$'\\x{:02x}'\"", ch as u32)),
+                        ch => output.push(ch),
+                    }
+                }
+            }
+            Shell::Batch => {
+                for ch in string.chars() {
+                    match ch {
+                        '%' => output.push_str("%%"),
+                        // Delayed expansion would otherwise treat '!' as the start of a variable
+                        '!' => output.push_str("^!"),
+                        '\n' | '\r' => return Err(Diagnostic::error(codes::UNREPRESENTABLE_STRING, "Batch cannot represent a line break in this string", span)
+                            .with_help("line breaks are only supported in strings passed to print()")),
+                        ch => output.push(ch),
+                    }
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn check_condition_type(&self, statement: &Stmt) -> Result<String, Diagnostic> {
+        let condition_type = self.get_condition_type(statement)?;
+        if !["int", "float", "str", "bool"].contains(&condition_type.as_str()) {
+            return Err(Diagnostic::error(codes::UNSUPPORTED_CONDITION_TYPE, format!("Unsupported condition type: {}", condition_type), statement.span)
+                .with_help("supported condition types are 'int', 'float', 'str' and 'bool'"));
+        }
+
+        Ok(condition_type)
+    }
+
+    fn get_condition_type(&self, statement: &Stmt) -> Result<String, Diagnostic> {
+        match &statement.kind {
+            StmtKind::Let { variable_type, .. } => Ok(variable_type.to_string()),
+            StmtKind::If { condition_type, .. } => Ok(condition_type.to_string()),
+            StmtKind::While { condition_type, .. } => Ok(condition_type.to_string()),
+            _ => Err(Diagnostic::error(codes::MISSING_CONDITION_TYPE, "Operators can only be used where a type is known", statement.span)
+                .with_help("move the expression into a typed 'let', 'if' or 'while'")),
+        }
+    }
+}
+
+fn check_variable_type(kind: &str, variable_type: &str, span: Span) -> Result<(), Diagnostic> {
+    if !["int", "float", "str", "bool"].contains(&variable_type) {
+        return Err(Diagnostic::error(codes::UNSUPPORTED_VARIABLE_TYPE, format!("Unsupported {} type: {}", kind, variable_type), span)
+            .with_help("supported variable types are 'int', 'float', 'str' and 'bool'"));
+    }
+
+    Ok(())
+}
+
+// Whether a value of type `found` can be used where `expected` is declared; ints widen to floats
+fn is_assignable(expected: &str, found: &str) -> bool {
+    expected == found || (expected == "float" && found == "int")
+}
+
+// Whether a Batch loop body jumps with goto: to break or continue, or through the labels of a nested loop
+fn needs_labels(body: &[Stmt]) -> bool {
+    body.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Break | StmtKind::Continue | StmtKind::While { .. } => true,
+        StmtKind::For { body, .. } => needs_labels(body),
+        StmtKind::If { then_branch, else_branch, .. } => needs_labels(then_branch) || else_branch.as_deref().is_some_and(needs_labels),
+        StmtKind::With { body, .. } => needs_labels(body),
+        _ => false,
+    })
+}
+
+// Whether an expression calculates a number, rather than naming or writing one out
+fn is_arithmetic(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Binary { operator, .. } => !operator.is_logical() && !operator.is_comparison(),
+        ExprKind::Unary { operator, .. } => *operator == UnaryOp::Negate,
+        _ => false,
+    }
+}
+
+// Calls to user functions anywhere in an expression
+fn count_user_calls(expr: &Expr) -> usize {
+    match &expr.kind {
+        ExprKind::Binary { left, right, .. } => count_user_calls(left) + count_user_calls(right),
+        ExprKind::Unary { operand, .. } => count_user_calls(operand),
+        ExprKind::Index { target, index } => count_user_calls(target) + count_user_calls(index),
+        ExprKind::Interpolated(parts) => parts.iter().map(|part| match part {
+            InterpolatedPart::Expr(expr) => count_user_calls(expr),
+            InterpolatedPart::Literal(_) => 0,
+        }).sum(),
+        ExprKind::Call { name, args } => {
+            let own = usize::from(!STD_FUNCTIONS.contains(&name.as_str()));
+            own + args.iter().map(count_user_calls).sum::<usize>()
+        }
+        _ => 0,
+    }
+}
+
+// Keeps the decimal point, so a float such as 2.0 isn't mistaken for an int in the output
+fn format_float(n: f64) -> String {
+    let text = n.to_string();
+
+    if text.contains('.') {
+        text
+    }
+    else {
+        format!("{}.0", text)
+    }
+}
+
+// Adds text to the lines of a Batch print, starting a new line at each line break
+fn push_echo_text(lines: &mut Vec<String>, text: &str) {
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            lines.push(String::new());
+        }
+        lines.last_mut().unwrap().push_str(&escape_batch_echo(line.trim_end_matches('\r')));
+    }
+}
+
+// 'echo(' prints the rest of the line as it is, where 'echo ' would treat blank text, 'on', 'off'
+// and '/?' as commands to echo itself
+fn echo_lines(lines: Vec<String>) -> String {
+    let mut output = String::new();
+
+    for line in lines {
+        output.push_str(format!("echo({}\n", line).as_str());
+    }
+
+    output
+}
+
+// Escapes text for an unquoted Batch echo, where the special characters need a caret
+fn escape_batch_echo(string: &str) -> String {
+    let mut output = String::new();
+
+    for ch in string.chars() {
+        match ch {
+            '^' | '&' | '|' | '<' | '>' | '(' | ')' => {
+                output.push('^');
+                output.push(ch);
+            }
+            '%' => output.push_str("%%"),
+            // Escaped twice, since the caret is consumed before delayed expansion sees the '!'
+            '!' => output.push_str("^^!"),
+            ch => output.push(ch),
+        }
+    }
+
+    output
 }
 
 fn indent<T:  AsRef<str>>(output: T) -> String {
-    let output = output.as_ref();
-    format!("   {}", output)
-}
\ No newline at end of file
+    let mut indented = String::new();
+    let mut heredoc: Option<&str> = None;
+
+    for line in output.as_ref().split_inclusive('\n') {
+        // Heredoc bodies are copied verbatim, otherwise the indentation would end up in the text
+        if let Some(delimiter) = heredoc {
+            if line.trim_end_matches('\n') == delimiter {
+                heredoc = None;
+            }
+            indented.push_str(line);
+            continue;
+        }
+
+        heredoc = line
+            .trim_end()
+            .rsplit_once("<<'")
+            .and_then(|(_, delimiter)| delimiter.strip_suffix('\''));
+
+        if line.trim().is_empty() {
+            indented.push_str(line);
+        }
+        else {
+            indented.push_str("   ");
+            indented.push_str(line);
+        }
+    }
+
+    indented
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn compiler(source: &str, shell: Shell) -> Compiler {
+        let statements = Parser::from_stream(Lexer::new(source)).parse().expect("source should parse");
+        let os = match shell {
+            Shell::Batch => OS::Windows,
+            Shell::Bash => OS::Linux,
+        };
+        Compiler::new(statements, os, shell)
+    }
+
+    fn compile(source: &str, shell: Shell) -> Result<String, Diagnostic> {
+        compiler(source, shell).compile()
+    }
+
+    fn assert_compiles(source: &str, shell: Shell, expected: &str) {
+        let output = compile(source, shell).unwrap_or_else(|e| panic!("{:?} failed to compile for {:?}: {}", source, shell, e));
+        assert!(output.contains(expected), "expected {:?} in the {:?} output:\n{}", expected, shell, output);
+    }
+
+    #[test]
+    fn batch_comparisons_work_out_arithmetic_first() {
+        let source = "let int count = 3;\nif int(count % 2 == 1) {\n    print(\"odd\")\n}\n";
+        assert_compiles(source, Shell::Batch, "set /a __tmp_0=!count! %% 2\nif !__tmp_0! EQU 1 (\n");
+        assert_compiles(source, Shell::Bash, "if [[ $((${count} % 2)) -eq 1 ]]; then\n");
+
+        let source = "let int a = 1;\nlet bool small = -a < a + 1;\n";
+        assert_compiles(source, Shell::Batch, "set /a __tmp_0=-(!a!)\nset /a __tmp_1=!a! + 1\n");
+    }
+
+    #[test]
+    fn floats_are_stored_with_the_same_decimal_places() {
+        let source = "let float a = 1.5;\nlet int n = 2;\nlet float b = n;\nlet float c = a + b;\n";
+        let bash = compiler(source, Shell::Bash).float_precision(3).compile().unwrap();
+        let batch = compiler(source, Shell::Batch).float_precision(3).compile().unwrap();
+
+        assert!(bash.contains("a=1.500\n") && batch.contains("set \"a=1.500\"\n"), "{}\n{}", bash, batch);
+        assert!(bash.contains("b=${n}.000\n") && batch.contains("set \"b=!n!.000\"\n"), "{}\n{}", bash, batch);
+        assert!(bash.contains("printf \\\"%.3f\\\"") && batch.contains("ToString('F3'"), "{}\n{}", bash, batch);
+    }
+
+    #[test]
+    fn float_variables_are_refused_in_int_arithmetic() {
+        for shell in [Shell::Bash, Shell::Batch] {
+            for source in ["let float f = 1.5;\nlet int x = f;\n", "let float f = 1.5;\nlet int x = f + 1;\n", "let float f = 1.5;\nlet int x = 0;\nx += f;\n"] {
+                let error = compile(source, shell).unwrap_err();
+                assert_eq!(error.code, codes::FLOAT_NOT_ALLOWED, "compiling {:?} for {:?}", source, shell);
+            }
+        }
+    }
+
+    #[test]
+    fn written_text_keeps_no_quotes_on_batch() {
+        let source = "let str name = \"bob\";\nwrite_file(path(\"out.txt\"), \"Hi ${name}\")\nappend_file(path(\"out.txt\"), concat(\"name=\", name))\n";
+        assert_compiles(source, Shell::Batch, "(\n   echo(Hi !name!\n) > \"\\out.txt\"\n");
+        assert_compiles(source, Shell::Batch, "(\n   echo(name=!name!\n) >> \"\\out.txt\"\n");
+        assert_compiles(source, Shell::Bash, "echo \"Hi ${name}\" > \"/out.txt\"\n");
+    }
+
+    #[test]
+    fn double_minus_negates_twice() {
+        let source = "let int y = 3;\nlet int x = --y;\nx--;\n";
+        assert_compiles(source, Shell::Bash, "x=$((-($((-(${y}))))))\n((x-=1))\n");
+        assert_compiles(source, Shell::Batch, "set /a x=-(-(!y!))\nset /a x-=1\n");
+    }
+
+    fn assert_error(source: &str, code: &str) {
+        for shell in [Shell::Bash, Shell::Batch] {
+            match compile(source, shell) {
+                Ok(output) => panic!("{:?} compiled for {:?}, expected {}:\n{}", source, shell, code, output),
+                Err(error) => assert_eq!(error.code, code, "compiling {:?} for {:?}: {}", source, shell, error),
+            }
+        }
+    }
+
+    #[test]
+    fn returned_values_are_read_from_ret() {
+        let source = "fn add(int a, int b) -> int {\n    return a + b;\n}\nlet int total = add(1, 2) + add(3, 4);\n";
+        assert_compiles(source, Shell::Bash, "   __ret=$((${a} + ${b}))\n   return\n}\n");
+        assert_compiles(source, Shell::Bash, "add 1 2 \n__ret_0=${__ret}\nadd 3 4 \ntotal=$((${__ret_0} + ${__ret}))\n");
+        assert_compiles(source, Shell::Batch, "   set /a __ret=!a! + !b!\n   for /f \"delims=\" %%a in (\"=!__ret!\") do endlocal & set \"__ret%%a\"\n   goto :eof\n");
+        assert_compiles(source, Shell::Batch, "call :add 1 2 \nset \"__ret_0=!__ret!\"\ncall :add 3 4 \nset /a total=!__ret_0! + !__ret!\n");
+    }
+
+    #[test]
+    fn batch_functions_follow_the_main_script() {
+        let source = "fn greet() {\n    print(\"hi\")\n}\ngreet()\nprint(\"done\")\n";
+        let output = compile(source, Shell::Batch).unwrap();
+        assert!(output.ends_with("call :greet \necho(done\ngoto :eof\n:greet\n   setlocal\n   echo(hi\n   goto :eof\n"), "{}", output);
+    }
+
+    #[test]
+    fn functions_keep_their_own_variables() {
+        let source = "let int count = 0;\nfn bump(int by) {\n    let int next = count + by;\n    count = next;\n}\nbump(2)\n";
+        assert_compiles(source, Shell::Bash, "   local by=$1\n   local next\n");
+        assert_compiles(source, Shell::Batch, "   setlocal\n   set \"by=%~1\"\n");
+        assert_compiles(source, Shell::Batch, "   for /f \"delims=\" %%a in (\"=!count!\") do endlocal & set \"count%%a\"\n   goto :eof\n");
+    }
+
+    #[test]
+    fn return_outside_a_function_is_an_error() {
+        assert_error("return 1;\n", codes::RETURN_OUTSIDE_FUNCTION);
+    }
+
+    #[test]
+    fn calls_on_the_right_of_a_logical_operator_are_errors() {
+        let function = "fn pos(int a) -> bool {\n    return a > 0;\n}\nlet int a = 3;\n";
+        assert_error(&format!("{}if bool(a > 5 && pos(a)) {{\n    print(a)\n}}\n", function), codes::CALL_IN_SHORT_CIRCUIT);
+        assert_error(&format!("{}let bool b = a > 5 || pos(a);\n", function), codes::CALL_IN_SHORT_CIRCUIT);
+
+        let source = format!("{}if bool(pos(a) && a > 5) {{\n    print(a)\n}}\n", function);
+        assert_compiles(&source, Shell::Bash, "pos \"${a}\" \nif [[ ${__ret} -eq 1 && ${a} -gt 5 ]]; then\n");
+    }
+
+    #[test]
+    fn typed_parameters_are_bound_without_quotes() {
+        let source = "fn greet(str name) -> str {\n    return \"Hello ${name}\";\n}\nlet str message = greet(\"bob\");\n";
+        assert_compiles(source, Shell::Bash, "   local name=$1\n   __ret=\"Hello ${name}\"\n");
+        assert_compiles(source, Shell::Bash, "greet \"bob\" \nmessage=${__ret}\n");
+        assert_compiles(source, Shell::Batch, "   set \"name=%~1\"\n   set \"__ret=Hello !name!\"\n");
+        assert_compiles(source, Shell::Batch, "call :greet \"bob\" \nset \"message=!__ret!\"\n");
+    }
+
+    #[test]
+    fn calls_are_checked_against_the_signature() {
+        let function = "fn show(int a) {\n    print(a)\n}\n";
+        assert_error(&format!("{}show(1, 2)\n", function), codes::ARGUMENT_COUNT);
+        assert_error(&format!("{}show(\"one\")\n", function), codes::INVALID_ARGUMENT_TYPE);
+        assert_error("fn show(number a) {\n    print(a)\n}\n", codes::UNSUPPORTED_VARIABLE_TYPE);
+    }
+
+    #[test]
+    fn returned_values_match_the_return_type() {
+        assert_error("fn f() -> int {\n    return \"x\";\n}\n", codes::RETURN_TYPE_MISMATCH);
+        assert_error("fn f() -> int {\n    return;\n}\n", codes::RETURN_TYPE_MISMATCH);
+        assert_error("fn f() -> number {\n    return 1;\n}\n", codes::UNSUPPORTED_VARIABLE_TYPE);
+    }
+
+    #[test]
+    fn ranges_count_up_to_their_end() {
+        let source = "for i in 0..3 {\n    print(i)\n}\n";
+        assert_compiles(source, Shell::Bash, "for ((i=0; i<3; i++)); do\n   echo \"${i}\"\ndone\n");
+        assert_compiles(source, Shell::Batch, "for /l %%i in (0,1,2) do (\n   set /a i=%%i\n   echo(!i!\n)\n");
+
+        let source = "for k in 5..0 step -2 {\n    print(k)\n}\n";
+        assert_compiles(source, Shell::Bash, "for ((k=5; k>0; k+=-2)); do\n");
+        assert_compiles(source, Shell::Batch, "for /l %%k in (5,-2,1) do (\n");
+    }
+
+    #[test]
+    fn variable_bounds_are_evaluated_once() {
+        let source = "let int n = 4;\nfor i in 0..n + 1 {\n    print(i)\n}\n";
+        assert_compiles(source, Shell::Bash, "__for_end_0=$((${n} + 1))\nfor ((i=0; i<${__for_end_0}; i++)); do\n");
+        assert_compiles(source, Shell::Batch, "set /a __for_end_0=!n! + 1 - 1\nfor /l %%i in (0,1,!__for_end_0!) do (\n");
+    }
+
+    #[test]
+    fn batch_ranges_around_nested_loops_count_with_labels() {
+        let source = "for i in 0..3 {\n    while int(i < 2) {\n        i++;\n    }\n}\n";
+        assert_compiles(source, Shell::Batch, "set /a i=0\n:for_loop_0\n   if !i! GEQ 3 goto :for_end_0\n   :while_loop_1\n");
+        assert_compiles(source, Shell::Batch, ":for_next_0\n   set /a i+=1\n   goto :for_loop_0\n:for_end_0\n");
+
+        let source = "for i in 0..3 {\n    for j in 0..3 {\n        if int(j == 1) {\n            break;\n        }\n    }\n}\n";
+        let output = compile(source, Shell::Batch).unwrap();
+        assert!(!output.contains("for /l"), "{}", output);
+    }
+
+    #[test]
+    fn malformed_ranges_are_errors() {
+        assert_error("for i in 0..3 step 0 {\n    print(i)\n}\n", codes::INVALID_RANGE);
+        assert_error("let int s = 2;\nfor i in 0..3 step s {\n    print(i)\n}\n", codes::INVALID_RANGE);
+        assert_error("for i in 0..2.5 {\n    print(i)\n}\n", codes::INVALID_RANGE);
+        assert_error("for i in 5..9223372036854775807 step -1 {\n    print(i)\n}\n", codes::INVALID_RANGE);
+    }
+
+    #[test]
+    fn while_loops_break_and_continue() {
+        let source = "let int x = 0;\nwhile int(x < 10) {\n    x++;\n    if int(x == 2) {\n        continue;\n    }\n    if int(x == 5) {\n        break;\n    }\n}\n";
+        assert_compiles(source, Shell::Bash, "   if [[ ${x} -eq 2 ]]; then\n      continue\n   fi\n   if [[ ${x} -eq 5 ]]; then\n      break\n   fi\n");
+        assert_compiles(source, Shell::Batch, "      if !x! EQU 2 (\n         goto :while_loop_0\n      )\n      if !x! EQU 5 (\n         goto :while_end_0\n      )\n");
+    }
+
+    #[test]
+    fn batch_ranges_that_jump_count_with_labels() {
+        let source = "for i in 0..4 {\n    if int(i == 1) {\n        continue;\n    }\n    if int(i == 3) {\n        break;\n    }\n}\n";
+        assert_compiles(source, Shell::Bash, "for ((i=0; i<4; i++)); do\n   if [[ ${i} -eq 1 ]]; then\n      continue\n   fi\n");
+        assert_compiles(source, Shell::Batch, "   if !i! EQU 1 (\n      goto :for_next_0\n   )\n   if !i! EQU 3 (\n      goto :for_end_0\n   )\n:for_next_0\n");
+    }
+
+    #[test]
+    fn loop_control_outside_a_loop_is_an_error() {
+        assert_error("break;\n", codes::LOOP_CONTROL_OUTSIDE_LOOP);
+        assert_error("if int(1 == 1) {\n    continue;\n}\n", codes::LOOP_CONTROL_OUTSIDE_LOOP);
+
+        // A loop around a definition doesn't reach into the function
+        assert_error("for i in 0..2 {\n    fn stop() {\n        break;\n    }\n}\n", codes::LOOP_CONTROL_OUTSIDE_LOOP);
+    }
+}
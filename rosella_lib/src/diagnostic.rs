@@ -0,0 +1,312 @@
+use std::error::Error;
+use std::fmt::{self, Write};
+
+use super::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+// Secondary location that helps explain the primary span
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// Replacement text for a span that would fix the diagnostic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub span: Span,
+    pub message: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new<T: Into<String>>(code: &'static str, severity: Severity, message: T, span: Span) -> Self {
+        Diagnostic {
+            code,
+            severity,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn error<T: Into<String>>(code: &'static str, message: T, span: Span) -> Self {
+        Diagnostic::new(code, Severity::Error, message, span)
+    }
+
+    pub fn warning<T: Into<String>>(code: &'static str, message: T, span: Span) -> Self {
+        Diagnostic::new(code, Severity::Warning, message, span)
+    }
+
+    // Error with no place in the source, such as a file that could not be read
+    pub fn unlocated<T: Into<String>>(code: &'static str, message: T) -> Self {
+        Diagnostic::new(code, Severity::Error, message, Span::default())
+    }
+
+    pub fn with_label<T: Into<String>>(mut self, span: Span, message: T) -> Self {
+        self.labels.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note<T: Into<String>>(mut self, note: T) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help<T: Into<String>>(mut self, help: T) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_suggestion<T: Into<String>, U: Into<String>>(mut self, span: Span, message: T, replacement: U) -> Self {
+        self.suggestions.push(Suggestion { span, message: message.into(), replacement: replacement.into() });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // Lines are 1-based, so only unlocated diagnostics sit on line 0
+    pub fn has_location(&self) -> bool {
+        self.span.line != 0
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.has_location() {
+            write!(f, "{}: ", self.span)?;
+        }
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+impl Error for Diagnostic {}
+
+// ANSI escape sequences used when colour is enabled
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const GREEN: &str = "\x1b[1;32m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Renders diagnostics as `file:line:col` followed by the offending source line and a caret underline
+pub struct Renderer<'a> {
+    file_name: &'a str,
+    source: &'a str,
+    colour: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(file_name: &'a str, source: &'a str) -> Self {
+        Renderer {
+            file_name,
+            source,
+            colour: false,
+        }
+    }
+
+    pub fn colour(mut self, colour: bool) -> Self {
+        self.colour = colour;
+        self
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.colour {
+            format!("{}{}{}", style, text, RESET)
+        }
+        else {
+            text.to_string()
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut output = String::new();
+        let span = diagnostic.span;
+
+        let style = match diagnostic.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => GREEN,
+        };
+
+        // Size the gutter for the widest line number shown
+        let widest_line = diagnostic.labels.iter().map(|label| label.span.line).fold(span.line, usize::max);
+        let gutter = " ".repeat(widest_line.to_string().len());
+
+        let header = format!("{}[{}]", diagnostic.severity, diagnostic.code);
+        let _ = writeln!(output, "{}{}", self.paint(style, &header), self.paint(BOLD, &format!(": {}", diagnostic.message)));
+
+        if diagnostic.has_location() {
+            let _ = writeln!(output, "{}{} {}:{}:{}", gutter, self.paint(BLUE, "-->"), self.file_name, span.line, span.column);
+            let _ = writeln!(output, "{} {}", gutter, self.paint(BLUE, "|"));
+
+            self.render_snippet(&mut output, &gutter, span, '^', style, "", true);
+            for label in &diagnostic.labels {
+                // Labels on the primary line share its source excerpt
+                let show_source = label.span.line != span.line;
+                self.render_snippet(&mut output, &gutter, label.span, '-', BLUE, &label.message, show_source);
+            }
+        }
+
+        for note in &diagnostic.notes {
+            let _ = writeln!(output, "{} {} note: {}", gutter, self.paint(BLUE, "="), note);
+        }
+
+        if let Some(help) = &diagnostic.help {
+            let _ = writeln!(output, "{} {} help: {}", gutter, self.paint(BLUE, "="), help);
+        }
+
+        for suggestion in &diagnostic.suggestions {
+            let _ = writeln!(output, "{} {} help: {}: `{}`", gutter, self.paint(BLUE, "="), suggestion.message, suggestion.replacement);
+        }
+
+        output
+    }
+
+    // Renders the diagnostic as a single line JSON object for editors and CI
+    pub fn render_json(&self, diagnostic: &Diagnostic) -> String {
+        let labels: Vec<String> = diagnostic.labels
+            .iter()
+            .map(|label| format!("{{\"message\":{},{}}}", json_string(&label.message), self.json_span(label.span)))
+            .collect();
+
+        let notes: Vec<String> = diagnostic.notes.iter().map(|note| json_string(note)).collect();
+
+        let suggestions: Vec<String> = diagnostic.suggestions
+            .iter()
+            .map(|suggestion| format!(
+                "{{\"message\":{},\"replacement\":{},{}}}",
+                json_string(&suggestion.message),
+                json_string(&suggestion.replacement),
+                self.json_span(suggestion.span)))
+            .collect();
+
+        let help = match &diagnostic.help {
+            Some(help) => json_string(help),
+            None => "null".to_string(),
+        };
+
+        let location = if diagnostic.has_location() {
+            self.json_span(diagnostic.span)
+        }
+        else {
+            "\"line\":null,\"column\":null,\"end_line\":null,\"end_column\":null,\"byte_start\":null,\"byte_end\":null".to_string()
+        };
+
+        format!(
+            "{{\"code\":{},\"severity\":{},\"message\":{},\"file\":{},{},\"labels\":[{}],\"notes\":[{}],\"help\":{},\"suggestions\":[{}]}}",
+            json_string(diagnostic.code),
+            json_string(&diagnostic.severity.to_string()),
+            json_string(&diagnostic.message),
+            json_string(self.file_name),
+            location,
+            labels.join(","),
+            notes.join(","),
+            help,
+            suggestions.join(","),
+        )
+    }
+
+    fn json_span(&self, span: Span) -> String {
+        let (end_line, end_column) = self.line_column(span.end);
+        format!(
+            "\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{},\"byte_start\":{},\"byte_end\":{}",
+            span.line, span.column, end_line, end_column, span.start, span.end)
+    }
+
+    // 1-based line and column of a byte offset into the source
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let before = &self.source[..offset];
+        let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+
+        (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_snippet(&self, output: &mut String, gutter: &str, span: Span, marker: char, style: &str, message: &str, show_source: bool) {
+        let bar = self.paint(BLUE, "|");
+
+        let start = span.start.min(self.source.len());
+        let line_start = self.source[..start].rfind('\n').map(|index| index + 1).unwrap_or(0);
+        let source_line = self.source[line_start..].lines().next().unwrap_or("");
+        let line_end = line_start + source_line.len();
+
+        if show_source {
+            let line_number = format!("{:>width$}", span.line, width = gutter.len());
+            let _ = writeln!(output, "{} {} {}", self.paint(BLUE, &line_number), bar, source_line);
+        }
+
+        // Keep tabs so the markers line up with the source line above
+        let padding: String = self.source[line_start..start.min(line_end)]
+            .chars()
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let underline_end = span.end.clamp(start, line_end.max(start));
+        let width = self.source[start..underline_end].chars().count().max(1);
+        let underline = marker.to_string().repeat(width);
+
+        if message.is_empty() {
+            let _ = writeln!(output, "{} {} {}{}", gutter, bar, padding, self.paint(style, &underline));
+        }
+        else {
+            let _ = writeln!(output, "{} {} {}{} {}", gutter, bar, padding, self.paint(style, &underline), self.paint(style, message));
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut output = String::from('"');
+
+    for ch in text.chars() {
+        match ch {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", ch as u32);
+            }
+            ch => output.push(ch),
+        }
+    }
+
+    output.push('"');
+    output
+}
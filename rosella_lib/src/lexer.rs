@@ -1,7 +1,15 @@
-use super::error::RosellaError;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::iter::FusedIterator;
+
+use super::codes;
+use super::diagnostic::Diagnostic;
+use super::span::Span;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token {
+pub enum Token<'a> {
     // Keywords
     Function,
     Let,
@@ -9,11 +17,21 @@ pub enum Token {
     Else,
     With,                   // E.g. with "windows", with "linux" 
     While,
+    For,
+    In,                     // E.g. for i in 0..10
+    Break,
+    Continue,
+    Return,
+    True,
+    False,
 
     // Identifier & Literals
-    Number(f64),
-    String(String),
-    Identifier(String),
+    Int(i64),
+    Float(f64),
+    String(Cow<'a, str>),
+    InterpolatedString(Vec<StringPart<'a>>),
+    RawString(Cow<'a, str>), // """ """
+    Identifier(Symbol<'a>),
     
     // Operators
     Assign,                 // =
@@ -21,12 +39,22 @@ pub enum Token {
     Minus,                  // -
     Multiply,               // *
     Divide,                 // /
+    Modulo,                 // %
+    PlusAssign,             // +=
+    MinusAssign,            // -=
+    MultiplyAssign,         // *=
+    DivideAssign,           // /=
+    Increment,              // ++
+    Decrement,              // --
     Equal,                  // ==
     NotEqual,               // !=
     LessThan,               // <
     GreaterThan,            // >
     LessThanEq,             // <=
     GreaterThanEq,          // >=
+    And,                    // &&
+    Or,                     // ||
+    Not,                    // !
     
     RawInstruction,         // |> 
     
@@ -42,87 +70,435 @@ pub enum Token {
     
     Comma,                  // ,
     Semicolon,              // ;
+    Arrow,                  // ->
+    DotDot,                 // ..
 
     // Comments
-    Comment,           // /*
+    Comment(&'a str),       // /* */ or //
 
+    #[allow(clippy::upper_case_acronyms)]
     EOF
 }
-pub struct Lexer {
-    input: Vec<char>,
-    position: usize,
-    current_character: Option<char>
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Function => write!(f, "'fn'"),
+            Token::Let => write!(f, "'let'"),
+            Token::If => write!(f, "'if'"),
+            Token::Else => write!(f, "'else'"),
+            Token::With => write!(f, "'with'"),
+            Token::While => write!(f, "'while'"),
+            Token::For => write!(f, "'for'"),
+            Token::In => write!(f, "'in'"),
+            Token::Break => write!(f, "'break'"),
+            Token::Continue => write!(f, "'continue'"),
+            Token::Return => write!(f, "'return'"),
+            Token::True => write!(f, "'true'"),
+            Token::False => write!(f, "'false'"),
+            Token::Int(n) => write!(f, "number {}", n),
+            Token::Float(n) => write!(f, "number {}", n),
+            Token::String(s) => write!(f, "string \"{}\"", s),
+            Token::InterpolatedString(_) => write!(f, "interpolated string"),
+            Token::RawString(_) => write!(f, "raw string"),
+            Token::Identifier(name) => write!(f, "identifier '{}'", name),
+            Token::Assign => write!(f, "'='"),
+            Token::Plus => write!(f, "'+'"),
+            Token::Minus => write!(f, "'-'"),
+            Token::Multiply => write!(f, "'*'"),
+            Token::Divide => write!(f, "'/'"),
+            Token::Modulo => write!(f, "'%'"),
+            Token::PlusAssign => write!(f, "'+='"),
+            Token::MinusAssign => write!(f, "'-='"),
+            Token::MultiplyAssign => write!(f, "'*='"),
+            Token::DivideAssign => write!(f, "'/='"),
+            Token::Increment => write!(f, "'++'"),
+            Token::Decrement => write!(f, "'--'"),
+            Token::Equal => write!(f, "'=='"),
+            Token::NotEqual => write!(f, "'!='"),
+            Token::LessThan => write!(f, "'<'"),
+            Token::GreaterThan => write!(f, "'>'"),
+            Token::LessThanEq => write!(f, "'<='"),
+            Token::GreaterThanEq => write!(f, "'>='"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Not => write!(f, "'!'"),
+            Token::RawInstruction => write!(f, "'|>'"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::LBrace => write!(f, "'{{'"),
+            Token::RBrace => write!(f, "'}}'"),
+            Token::LBraceSquare => write!(f, "'['"),
+            Token::RBraceSquare => write!(f, "']'"),
+            Token::Comma => write!(f, "','"),
+            Token::Semicolon => write!(f, "';'"),
+            Token::Arrow => write!(f, "'->'"),
+            Token::DotDot => write!(f, "'..'"),
+            Token::Comment(_) => write!(f, "comment"),
+            Token::EOF => write!(f, "end of file"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken<'a> {
+    pub token: Token<'a>,
+    pub span: Span,
+}
+
+// A piece of a string literal, either plain text or the tokens of a `${...}`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart<'a> {
+    Literal(Cow<'a, str>),
+    Code(Vec<SpannedToken<'a>>),
+}
+
+// An identifier borrowed from the source. Every occurrence of a name shares the slice
+// of its first occurrence, so equal symbols from one lexer usually compare by pointer
+#[derive(Debug, Clone, Copy, Eq)]
+pub struct Symbol<'a>(&'a str);
+
+impl<'a> Symbol<'a> {
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0) || self.0 == other.0
+    }
+}
+
+impl Hash for Symbol<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl fmt::Display for Symbol<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+#[derive(Default)]
+struct Interner<'a> {
+    symbols: HashMap<&'a str, Symbol<'a>, BuildHasherDefault<FnvHasher>>,
+}
+
+impl<'a> Interner<'a> {
+    fn intern(&mut self, text: &'a str) -> Symbol<'a> {
+        *self.symbols.entry(text).or_insert(Symbol(text))
+    }
+}
+
+// FNV-1a, which is much quicker than the default hasher on short keys like identifiers
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf29ce484222325)
+    }
 }
 
-impl Lexer {
-    pub fn new(input: &str) -> Self {
-        let characters: Vec<char> = input.chars().collect();
-        let current = characters.get(0).copied();
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
 
+pub struct Lexer<'a> {
+    source: &'a str,
+    current_character: Option<char>,
+
+    // Location of the current character
+    position: usize,
+    line: usize,
+    column: usize,
+
+    interner: Interner<'a>,
+
+    // Errors found while lexing `pending`, which are yielded before it
+    errors: VecDeque<Diagnostic>,
+    pending: Option<SpannedToken<'a>>,
+    finished: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
         Lexer {
-            input: characters,
+            source,
+            current_character: source.chars().next(),
             position: 0,
-            current_character: current
+            line: 1,
+            column: 1,
+            interner: Interner::default(),
+            errors: VecDeque::new(),
+            pending: None,
+            finished: false,
         }
     }
 
     fn advance(&mut self) {
-        self.position += 1;
-        self.current_character = self.input.get(self.position).copied();
+        if let Some(ch) = self.current_character {
+            self.position += ch.len_utf8();
+            if ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+            else {
+                self.column += 1;
+            }
+        }
+
+        self.current_character = self.source[self.position..].chars().next();
     }
 
-    fn read_number(&mut self) -> f64 {
-        let mut string: String = String::new();
+    fn peek(&self) -> Option<char> {
+        let next = self.position + self.current_character.map_or(0, char::len_utf8);
+        self.source[next..].chars().next()
+    }
 
-        // Read Each Number
-        while let Some(ch) = self.current_character {
-            if ch.is_ascii_digit() || ch == '.' {
-                string.push(ch);
-                self.advance();
-            } 
-            else {
-                break;
+    // Zero-width span at the current character
+    fn current_span(&self) -> Span {
+        Span::new(self.position, self.position, self.line, self.column)
+    }
+
+    // Span from a previously recorded start up to the current character
+    fn span_from(&self, start: Span) -> Span {
+        Span::new(start.start, self.position, start.line, start.column)
+    }
+
+    fn read_number(&mut self) -> Token<'a> {
+        let start = self.current_span();
+
+        // Read the whole literal, including any letters, so a malformed number is reported as one unit
+        loop {
+            match self.current_character {
+                Some(ch) if ch.is_ascii_alphanumeric() || ch == '_' => self.advance(),
+                // A '.' only continues the number when a digit follows, so `0..10` is still a range
+                Some('.') if self.peek().is_some_and(|ch| ch.is_ascii_digit()) => self.advance(),
+                _ => break,
             }
         }
 
-        // Parse Number
-        let result: f64 = match string.parse() {
-            Ok(res) => res,
-            Err(_) => {
-                eprintln!("Cannot parse number: {}", string);
-                0.0
+        let text = &self.source[start.start..self.position];
+        match parse_number(text) {
+            Ok(token) => token,
+            Err(help) => {
+                self.errors.push_back(Diagnostic::error(codes::MALFORMED_NUMBER, format!("Malformed number literal '{}'", text), self.span_from(start))
+                    .with_help(help));
+                Token::Int(0)
+            }
+        }
+    }
+
+    fn at_raw_string_quotes(&self) -> bool {
+        self.source[self.position..].starts_with("\"\"\"")
+    }
+
+    fn read_raw_string(&mut self) -> Token<'a> {
+        let start = self.current_span();
+
+        // Skip the opening '"""'
+        for _ in 0..3 {
+            self.advance();
+        }
+
+        let content_start = self.position;
+        let content_end = loop {
+            match self.current_character {
+                Some('"') if self.at_raw_string_quotes() => {
+                    let end = self.position;
+                    for _ in 0..3 {
+                        self.advance();
+                    }
+                    break end;
+                }
+                Some(_) => self.advance(),
+                None => {
+                    self.errors.push_back(Diagnostic::error(codes::UNTERMINATED_STRING, "Unterminated raw string literal", Span::new(start.start, start.start + 3, start.line, start.column))
+                        .with_help("add a closing '\"\"\"' to end the string"));
+                    break self.position;
+                }
             }
         };
 
-        result
+        Token::RawString(dedent(&self.source[content_start..content_end]))
     }
 
-    fn read_string(&mut self) -> String {
-        let mut string: String = String::new();
+    fn read_string(&mut self) -> Token<'a> {
+        // Text stays borrowed from the source until an escape forces a copy
+        let mut string: Cow<'a, str> = Cow::Borrowed("");
+        let mut parts: Vec<StringPart<'a>> = Vec::new();
+        let start = self.current_span();
 
         // Skip Quote
         self.advance();
+        let mut segment_start = self.position;
 
-        while let Some(ch) = self.current_character {
-            // Skip Last Quote
-            if ch == '"' {
+        loop {
+            match self.current_character {
+                // Skip Last Quote
+                Some('"') => {
+                    push_slice(&mut string, &self.source[segment_start..self.position]);
+                    self.advance();
+                    break;
+                }
+                Some('\\') => {
+                    push_slice(&mut string, &self.source[segment_start..self.position]);
+                    if let Some(ch) = self.read_escape() {
+                        string.to_mut().push(ch);
+                    }
+                    segment_start = self.position;
+                }
+                Some('$') if self.peek() == Some('{') => {
+                    push_slice(&mut string, &self.source[segment_start..self.position]);
+                    if !string.is_empty() {
+                        parts.push(StringPart::Literal(std::mem::take(&mut string)));
+                    }
+                    parts.push(StringPart::Code(self.read_interpolation()));
+                    segment_start = self.position;
+                }
+                Some(_) => self.advance(),
+                None => {
+                    push_slice(&mut string, &self.source[segment_start..self.position]);
+                    self.errors.push_back(Diagnostic::error(codes::UNTERMINATED_STRING, "Unterminated string literal", Span::new(start.start, start.start + 1, start.line, start.column))
+                        .with_help("add a closing '\"' to end the string"));
+                    break;
+                }
+            }
+        }
+
+        if parts.is_empty() {
+            return Token::String(string);
+        }
+
+        if !string.is_empty() {
+            parts.push(StringPart::Literal(string));
+        }
+        Token::InterpolatedString(parts)
+    }
+
+    // Lexes the code inside `${...}` up to its closing '}'
+    fn read_interpolation(&mut self) -> Vec<SpannedToken<'a>> {
+        let start = self.current_span();
+        let mut tokens: Vec<SpannedToken<'a>> = Vec::new();
+        let mut depth = 0;
+
+        // Skip '${'
+        self.advance();
+        self.advance();
+
+        loop {
+            while matches!(self.current_character, Some(ch) if ch.is_ascii_whitespace() && ch != '\n') {
                 self.advance();
+            }
+
+            // The interpolation can't run past the end of its string
+            if matches!(self.current_character, Some('"') | Some('\n') | None) {
+                self.errors.push_back(Diagnostic::error(codes::UNTERMINATED_INTERPOLATION, "Unterminated interpolation", Span::new(start.start, start.start + 2, start.line, start.column))
+                    .with_help("add '}' to close the interpolation"));
                 break;
             }
 
-            string.push(ch);
+            let token = self.next_token();
+            match token.token {
+                Token::RBrace if depth == 0 => break,
+                Token::RBrace => depth -= 1,
+                Token::LBrace => depth += 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+
+        tokens
+    }
+
+    // Reads an escape sequence starting at the backslash, reporting any that are invalid
+    fn read_escape(&mut self) -> Option<char> {
+        let start = self.current_span();
+        self.advance();
+
+        let escaped = match self.current_character {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('r') => Some('\r'),
+            Some('"') => Some('"'),
+            Some('\\') => Some('\\'),
+            Some('$') => Some('$'),
+            Some('u') => {
+                self.advance();
+                return self.read_unicode_escape(start);
+            }
+            _ => None,
+        };
+
+        match escaped {
+            Some(ch) => {
+                self.advance();
+                Some(ch)
+            }
+            None => {
+                let sequence = match self.current_character {
+                    Some(ch) => {
+                        self.advance();
+                        format!("\\{}", ch)
+                    }
+                    None => "\\".to_string(),
+                };
+                self.errors.push_back(Diagnostic::error(codes::INVALID_ESCAPE, format!("Unknown escape sequence '{}'", sequence), self.span_from(start))
+                    .with_help("valid escapes are \\n, \\t, \\r, \\\", \\\\, \\$ and \\u{...}; use '\\\\' for a literal backslash"));
+                None
+            }
+        }
+    }
+
+    // Reads the `{XXXX}` part of a `\u{XXXX}` escape
+    fn read_unicode_escape(&mut self, start: Span) -> Option<char> {
+        let mut digits = String::new();
+        let mut closed = false;
+
+        if self.current_character == Some('{') {
             self.advance();
+            while let Some(ch) = self.current_character {
+                if ch == '}' {
+                    self.advance();
+                    closed = true;
+                    break;
+                }
+                if ch == '"' || ch == '\n' {
+                    break;
+                }
+                digits.push(ch);
+                self.advance();
+            }
         }
 
-        string
+        let valid = closed && (1..=6).contains(&digits.len()) && digits.chars().all(|ch| ch.is_ascii_hexdigit());
+        let ch = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32);
+        match ch {
+            Some(ch) if valid => Some(ch),
+            _ => {
+                self.errors.push_back(Diagnostic::error(codes::INVALID_ESCAPE, "Invalid unicode escape", self.span_from(start))
+                    .with_help("unicode escapes are written as \\u{...} with 1 to 6 hex digits, e.g. \\u{1F600}"));
+                None
+            }
+        }
     }
 
-    fn read_identifer(&mut self) -> String {
-        let mut string: String = String::new();
+    fn read_identifer(&mut self) -> &'a str {
+        let start = self.position;
 
         while let Some(ch) = self.current_character {
             if ch.is_ascii_alphanumeric() || ch == '_' {
-                string.push(ch);
                 self.advance();
             }
             else {
@@ -130,22 +506,30 @@ impl Lexer {
             }
         }
 
-        string
+        &self.source[start..self.position]
     }
 
-    fn determine_keyword(&self, text: String) -> Token {
-        match text.as_str() {
+    fn determine_keyword(&mut self, text: &'a str) -> Token<'a> {
+        match text {
             "fn" => Token::Function,
             "let" => Token::Let,
             "if" => Token::If,
             "else" => Token::Else,
             "with" => Token::With,
             "while" => Token::While,
-            _ => Token::Identifier(text.to_string())
+            "for" => Token::For,
+            "in" => Token::In,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "return" => Token::Return,
+            "true" => Token::True,
+            "false" => Token::False,
+            _ => Token::Identifier(self.interner.intern(text))
         }
     }
 
-    fn determine_punctuation(&mut self, current_char: Option<char>) -> Result<Token, RosellaError> {        
+    fn determine_punctuation(&mut self, current_char: Option<char>) -> Result<Token<'a>, Diagnostic> {        
+        let start = self.current_span();
         self.advance();
 
         match current_char {
@@ -156,15 +540,70 @@ impl Lexer {
                 }
                 Ok(Token::Assign) 
             }
-            Some('+') => Ok(Token::Plus),
-            Some('-') => Ok(Token::Minus),
+            Some('+') => match self.current_character {
+                Some('=') => {
+                    self.advance();
+                    Ok(Token::PlusAssign)
+                }
+                Some('+') => {
+                    self.advance();
+                    Ok(Token::Increment)
+                }
+                _ => Ok(Token::Plus),
+            },
+            Some('-') => match self.current_character {
+                Some('=') => {
+                    self.advance();
+                    Ok(Token::MinusAssign)
+                }
+                Some('-') => {
+                    self.advance();
+                    Ok(Token::Decrement)
+                }
+                Some('>') => {
+                    self.advance();
+                    Ok(Token::Arrow)
+                }
+                _ => Ok(Token::Minus),
+            },
             Some('*') => {
+                if self.current_character == Some('=') {
+                    self.advance();
+                    return Ok(Token::MultiplyAssign)
+                }
                 Ok(Token::Multiply)
             },
+            Some('%') => Ok(Token::Modulo),
+            Some('.') => {
+                if self.current_character == Some('.') {
+                    self.advance();
+                    Ok(Token::DotDot)
+                }
+                else {
+                    Err(invalid_character('.', self.span_from(start))
+                        .with_help("use '..' for a range, e.g. 0..10"))
+                }
+            }
+            Some('&') => {
+                if self.current_character == Some('&') {
+                    self.advance();
+                    Ok(Token::And)
+                }
+                else {
+                    Err(invalid_character('&', self.span_from(start))
+                        .with_help("use '&&' for a logical and"))
+                }
+            }
             Some('/') => {
                 if self.current_character == Some('*') {
-                    self.consume_comment()?;
-                    return Ok(Token::Comment);                    
+                    Ok(Token::Comment(self.consume_comment(start)?))
+                }
+                else if self.current_character == Some('/') {
+                    Ok(Token::Comment(self.consume_line_comment()))
+                }
+                else if self.current_character == Some('=') {
+                    self.advance();
+                    Ok(Token::DivideAssign)
                 }
                 else {
                     Ok(Token::Divide)
@@ -181,7 +620,6 @@ impl Lexer {
                 }
             }
             Some('>') => {
-                self.advance();
                 if self.current_character == Some('=') {
                     self.advance();
                     Ok(Token::GreaterThanEq)
@@ -200,32 +638,67 @@ impl Lexer {
             Some(',') => Ok(Token::Comma),
             Some(';') => Ok(Token::Semicolon),
             //Some(_) => panic!("Unhandled Punctuation: {:?}", current_char),
-            Some(_) => Err(RosellaError::InvalidPunctuation(current_char)),
+            Some(ch) => Err(invalid_character(ch, self.span_from(start))),
             None => Ok(Token::EOF)
         }
     }
 
-    fn consume_comment(&mut self) -> Result<(), RosellaError> {
+    fn consume_comment(&mut self, start: Span) -> Result<&'a str, Diagnostic> {
         self.advance(); // Skip the initial '*'
+        let text_start = self.position;
+
         while let Some(ch) = self.current_character {
-            if ch == '*' {
+            if ch == '*' && self.peek() == Some('/') {
+                let text = &self.source[text_start..self.position];
                 self.advance();
-                if self.current_character == Some('/') {
-                    self.advance();
-                    return Ok(());
-                }
-            } else {
                 self.advance();
+                return Ok(text);
             }
+            self.advance();
         }
-        Err(RosellaError::ParseError("Expected */ to end comment".to_string()))
+        Err(Diagnostic::error(codes::UNTERMINATED_COMMENT, "Unterminated block comment", Span::new(start.start, start.start + 2, start.line, start.column))
+            .with_help("add '*/' to close the comment"))
     }
 
-    pub fn tokenise(&mut self) -> Result<Vec<Token>, RosellaError> {
-        let mut tokens: Vec<Token> = Vec::new();
+    fn consume_line_comment(&mut self) -> &'a str {
+        self.advance(); // Skip the second '/'
+        let text_start = self.position;
 
+        while let Some(ch) = self.current_character {
+            if ch == '\n' {
+                break;
+            }
+            self.advance();
+        }
+
+        &self.source[text_start..self.position]
+    }
+
+    // Lexes the whole input, collecting every error rather than stopping at the first
+    pub fn tokenise(&mut self) -> Result<Vec<SpannedToken<'a>>, Vec<Diagnostic>> {
+        let mut tokens: Vec<SpannedToken<'a>> = Vec::new();
+        let mut errors: Vec<Diagnostic> = Vec::new();
+
+        for result in self.by_ref() {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(tokens)
+        }
+        else {
+            Err(errors)
+        }
+    }
+
+    // Lexes the next token, skipping whitespace and recording any invalid characters
+    fn next_token(&mut self) -> SpannedToken<'a> {
         loop {
-            let token: Token = match self.current_character {
+            let start = self.current_span();
+            let token: Token<'a> = match self.current_character {
                 // Handle Whitespace
                 Some('\n') | Some('\t') | Some('\r') => {
                     self.advance();
@@ -236,12 +709,13 @@ impl Lexer {
                     continue;
                 }
 
-                Some(ch) if ch.is_ascii_digit() => Token::Number(self.read_number()),
-                Some(ch) if ch.is_alphabetic() || ch == '_' => {
+                Some(ch) if ch.is_ascii_digit() => self.read_number(),
+                Some(ch) if ch.is_ascii_alphabetic() || ch == '_' => {
                     let ident = self.read_identifer();
                     self.determine_keyword(ident)
                 },
-                Some('"') => Token::String(self.read_string()),
+                Some('"') if self.at_raw_string_quotes() => self.read_raw_string(),
+                Some('"') => self.read_string(),
                 Some('!') => {
                     self.advance();
                     if self.current_character == Some('=') {
@@ -249,7 +723,7 @@ impl Lexer {
                         Token::NotEqual
                     }
                     else {
-                        continue;
+                        Token::Not
                     }
                 }
                 Some('|') => {
@@ -258,27 +732,202 @@ impl Lexer {
                         self.advance();
                         Token::RawInstruction
                     }
+                    else if self.current_character == Some('|') {
+                        self.advance();
+                        Token::Or
+                    }
                     else {
+                        self.errors.push_back(invalid_character('|', self.span_from(start))
+                            .with_help("use '||' for a logical or, or '|>' for a raw instruction"));
                         continue;
                     }
                 }
                 Some(ch) if ch.is_ascii_punctuation() => match self.determine_punctuation(self.current_character) {
                     Ok(token) => token,
-                    Err(e) => return Err(e),
+                    Err(e) => {
+                        // Keep lexing so every invalid character is reported in one run
+                        self.errors.push_back(e);
+                        continue;
+                    }
+                },
+                Some(ch) => {
+                    self.advance();
+                    self.errors.push_back(invalid_character(ch, self.span_from(start)));
+                    continue;
                 },
-                Some(_) => Err(RosellaError::InvalidToken(self.current_character))?,
                 //Some(_) => panic!("Input does not match a valid token: {:?}", self.current_character),
 
                 None => Token::EOF
             };
-            
-            if token == Token::EOF {
-                tokens.push(token);
-                break;
-            }
-            tokens.push(token);
+
+            return SpannedToken { token, span: self.span_from(start) };
+        }
+    }
+}
+
+// Yields tokens up to and including EOF. Lexing carries on past an error, so every error
+// in the input is reported as an `Err` just before the token it was found in
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<SpannedToken<'a>, Diagnostic>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pending.is_none() && !self.finished {
+            let token = self.next_token();
+            self.finished = token.token == Token::EOF;
+            self.pending = Some(token);
+        }
+
+        match self.errors.pop_front() {
+            Some(e) => Some(Err(e)),
+            None => self.pending.take().map(Ok),
         }
+    }
+}
+
+impl FusedIterator for Lexer<'_> {}
+
+// Parses the text of a number literal, returning help for the error when it is malformed
+fn parse_number(text: &str) -> Result<Token<'static>, &'static str> {
+    let (digits, radix) = match text.get(..2) {
+        Some("0x") | Some("0X") => (&text[2..], 16),
+        Some("0b") | Some("0B") => (&text[2..], 2),
+        _ => (text, 10),
+    };
+
+    if digits.split('.').any(|part| part.starts_with('_') || part.ends_with('_')) {
+        return Err("underscores can only be used between digits");
+    }
+
+    if radix == 10 && digits.contains('.') {
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if fraction.contains('.') {
+            return Err("a number can only have one decimal point");
+        }
+        if !valid_digits(whole, 10) || !valid_digits(fraction, 10) {
+            return Err("a float is written as digits, a '.' and more digits, e.g. 1.5");
+        }
+
+        return digits.replace('_', "").parse().map(Token::Float).map_err(|_| "this is not a valid float");
+    }
+
+    if digits.is_empty() {
+        return Err("add digits after the prefix, e.g. 0xFF or 0b1010");
+    }
+    if !valid_digits(digits, radix) {
+        return Err(match radix {
+            16 => "hexadecimal literals can only contain 0-9, a-f and '_'",
+            2 => "binary literals can only contain 0, 1 and '_'",
+            _ => "decimal literals can only contain 0-9 and '_'; prefix hexadecimal with 0x and binary with 0b",
+        });
+    }
+
+    i64::from_str_radix(&digits.replace('_', ""), radix)
+        .map(Token::Int)
+        .map_err(|_| "integer literals must fit in a signed 64-bit integer")
+}
+
+fn valid_digits(digits: &str, radix: u32) -> bool {
+    !digits.is_empty() && digits.chars().all(|ch| ch == '_' || ch.is_digit(radix))
+}
+
+// Drops the line breaks next to the quotes of a raw string and the indentation shared by its lines,
+// so the literal can be indented along with the code around it
+fn dedent(text: &str) -> Cow<'_, str> {
+    let text = text.strip_prefix("\r\n").or_else(|| text.strip_prefix('\n')).unwrap_or(text);
+    let text = match text.rfind('\n') {
+        Some(index) if text[index..].trim().is_empty() => text[..index].trim_end_matches('\r'),
+        _ => text,
+    };
+
+    let indentation = text
+        .split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    if indentation == 0 && !text.contains('\r') {
+        return Cow::Borrowed(text);
+    }
+
+    let lines: Vec<&str> = text
+        .split('\n')
+        .map(|line| line.get(indentation..).unwrap_or("").trim_end_matches('\r'))
+        .collect();
+    Cow::Owned(lines.join("\n"))
+}
+
+// Appends a slice of the source to a string literal without copying while it is still borrowed
+fn push_slice<'a>(string: &mut Cow<'a, str>, slice: &'a str) {
+    if string.is_empty() {
+        *string = Cow::Borrowed(slice);
+    }
+    else if !slice.is_empty() {
+        string.to_mut().push_str(slice);
+    }
+}
+
+fn invalid_character(ch: char, span: Span) -> Diagnostic {
+    Diagnostic::error(codes::INVALID_CHARACTER, format!("Unexpected character '{}'", ch), span)
+        .with_help("remove this character or place it inside a string")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token<'_>> {
+        let tokens = Lexer::new(source).tokenise().expect("source should lex");
+        tokens.into_iter().map(|spanned| spanned.token).collect()
+    }
+
+    #[test]
+    fn valid_numbers() {
+        let cases = [
+            ("42", Token::Int(42)),
+            ("0xFF", Token::Int(255)),
+            ("0Xff", Token::Int(255)),
+            ("0b1010", Token::Int(10)),
+            ("1_000_000", Token::Int(1_000_000)),
+            ("9223372036854775807", Token::Int(i64::MAX)),
+            ("1.5", Token::Float(1.5)),
+            ("1_000.25", Token::Float(1000.25)),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(parse_number(text), Ok(expected), "parsing {:?}", text);
+        }
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        let cases = [
+            ("1.2.3", "a number can only have one decimal point"),
+            ("1_", "underscores can only be used between digits"),
+            ("_1", "underscores can only be used between digits"),
+            ("1._5", "underscores can only be used between digits"),
+            ("0x", "add digits after the prefix, e.g. 0xFF or 0b1010"),
+            ("0b102", "binary literals can only contain 0, 1 and '_'"),
+            ("0xFG", "hexadecimal literals can only contain 0-9, a-f and '_'"),
+            ("1e5", "decimal literals can only contain 0-9 and '_'; prefix hexadecimal with 0x and binary with 0b"),
+            ("9223372036854775808", "integer literals must fit in a signed 64-bit integer"),
+        ];
+
+        for (text, help) in cases {
+            assert_eq!(parse_number(text), Err(help), "parsing {:?}", text);
+        }
+    }
+
+    #[test]
+    fn ranges_are_not_floats() {
+        assert_eq!(tokens("0..10"), [Token::Int(0), Token::DotDot, Token::Int(10), Token::EOF]);
+        assert_eq!(tokens("1.5..2"), [Token::Float(1.5), Token::DotDot, Token::Int(2), Token::EOF]);
+    }
 
-        Ok(tokens)
+    #[test]
+    fn malformed_numbers_are_reported_once() {
+        let errors = Lexer::new("let int a = 1.2.3;").tokenise().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, codes::MALFORMED_NUMBER);
     }
 }
@@ -1,8 +1,18 @@
+// Diagnostics are only built on the error path, so their size is not a concern
+#![allow(clippy::result_large_err)]
+
 mod lexer;
 mod parser;
-mod error;
 mod compiler;
+mod span;
+mod diagnostic;
+pub mod codes;
+mod syntax;
 
-pub use lexer::Lexer;
+pub use lexer::{Lexer, SpannedToken, Token};
 pub use parser::{Parser, OS};
-pub use compiler::{Compiler, Shell};
\ No newline at end of file
+pub use compiler::{Compiler, Shell};
+pub use span::Span;
+pub use codes::explain;
+pub use diagnostic::{Diagnostic, Severity, Label, Suggestion, Renderer};
+pub use syntax::{SyntaxTree, SyntaxNode, SyntaxElement, SyntaxToken, Trivia, TriviaKind, NodeKind};
@@ -1,34 +1,131 @@
-use super::lexer::Token;
-use super::error::RosellaError;
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::lexer::{Token, SpannedToken, StringPart};
+use super::codes;
+use super::diagnostic::Diagnostic;
+use super::span::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
-    Number(f64),
+pub enum ExprKind {
+    Int(i64),
+    Float(f64),
+    Boolean(bool),
     String(String),
+    RawString(String),
+    Interpolated(Vec<InterpolatedPart>),
     Identifier(String),
     Binary {
         left: Box<Expr>,
         operator: BinaryOp,
         right: Box<Expr>,
     },
+    Unary {
+        operator: UnaryOp,
+        operand: Box<Expr>,
+    },
     Call {
         name: String,
         args: Vec<Expr>,
+    },
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpolatedPart {
+    Literal(String),
+    Expr(Expr),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOp {
     Add,
     Subtract,
     Multiply,
     Divide,
+    Modulo,
     Equal,
     NotEqual,
     LessThan,
     LessThanEq,
     GreaterThan,
-    GreaterThanEq
+    GreaterThanEq,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    pub fn is_logical(&self) -> bool {
+        matches!(self, BinaryOp::And | BinaryOp::Or)
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(self,
+            BinaryOp::Equal | BinaryOp::NotEqual
+            | BinaryOp::LessThan | BinaryOp::LessThanEq
+            | BinaryOp::GreaterThan | BinaryOp::GreaterThanEq)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssignOp {
+    Set,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Increment,
+    Decrement,
+}
+
+impl AssignOp {
+    // The arithmetic an update applies to the variable, or None for a plain '='
+    pub fn binary_op(&self) -> Option<BinaryOp> {
+        match self {
+            AssignOp::Set => None,
+            AssignOp::Add | AssignOp::Increment => Some(BinaryOp::Add),
+            AssignOp::Subtract | AssignOp::Decrement => Some(BinaryOp::Subtract),
+            AssignOp::Multiply => Some(BinaryOp::Multiply),
+            AssignOp::Divide => Some(BinaryOp::Divide),
+        }
+    }
+}
+
+impl fmt::Display for AssignOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssignOp::Set => write!(f, "="),
+            AssignOp::Add => write!(f, "+="),
+            AssignOp::Subtract => write!(f, "-="),
+            AssignOp::Multiply => write!(f, "*="),
+            AssignOp::Divide => write!(f, "/="),
+            AssignOp::Increment => write!(f, "++"),
+            AssignOp::Decrement => write!(f, "--"),
+        }
+    }
+}
+
+// A parameter of a function definition. One without a type accepts a value of any type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub param_type: Option<String>,
+    pub name: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -38,13 +135,28 @@ pub enum OS {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Stmt {
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+    // Comments written around the statement, carried through to the generated script
+    pub comments: Vec<String>,
+    pub trailing_comments: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
     Expression(Expr),
     Let {
         variable_type: String,
         name: String,
         value: Expr,
     },
+    // `value` is None for '++' and '--'
+    Assign {
+        name: String,
+        operator: AssignOp,
+        value: Option<Expr>,
+    },
     If {
         condition_type: String,
         condition: Expr,
@@ -60,114 +172,403 @@ pub enum Stmt {
         condition: Expr,
         body: Vec<Stmt>,
     },
+    // Counts from `start` up to but not including `end`
+    For {
+        variable: String,
+        start: Expr,
+        end: Expr,
+        step: Option<Expr>,
+        body: Vec<Stmt>,
+    },
     Function {
         name: String,
-        arguments: Option<Vec<Expr>>,
+        parameters: Vec<Param>,
+        return_type: Option<String>,
         body: Vec<Stmt>,
     },
+    Break,
+    Continue,
+    // `value` is None for a bare 'return;'
+    Return(Option<Expr>),
     RawInstruction(Vec<Expr>)
 }
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    position: usize
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, position: 0 }
+impl ExprKind {
+    // Short human readable name used in diagnostics
+    pub fn description(&self) -> String {
+        match self {
+            ExprKind::Int(_) => "a number".to_string(),
+            ExprKind::Float(_) => "a float".to_string(),
+            ExprKind::Boolean(value) => format!("'{}'", value),
+            ExprKind::String(_) => "a string".to_string(),
+            ExprKind::RawString(_) => "a raw string".to_string(),
+            ExprKind::Interpolated(_) => "an interpolated string".to_string(),
+            ExprKind::Identifier(name) => format!("the variable '{}'", name),
+            ExprKind::Binary { .. } => "a binary expression".to_string(),
+            ExprKind::Unary { .. } => "a unary expression".to_string(),
+            ExprKind::Call { name, .. } => format!("a call to '{}()'", name),
+            ExprKind::Index { .. } => "an index expression".to_string(),
+        }
     }
+}
 
-    fn current_token(&self) -> &Token {
-        match self.tokens.get(self.position) {
-            Some(token) => token,
-            None => &Token::EOF
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt { kind, span, comments: Vec::new(), trailing_comments: Vec::new() }
+    }
+}
+
+// A token waiting to be parsed, with the comments written just before it
+struct Buffered<'a> {
+    token: SpannedToken<'a>,
+    comments: Vec<String>,
+}
+
+pub struct Parser<'a> {
+    tokens: Box<dyn Iterator<Item = Result<SpannedToken<'a>, Diagnostic>> + 'a>,
+
+    // Tokens pulled from the stream but not yet consumed; the first is the current token
+    lookahead: VecDeque<Buffered<'a>>,
+    previous: Option<SpannedToken<'a>>,
+    position: usize,
+
+    errors: Vec<Diagnostic>,
+    fail_fast: bool,
+
+    // Source passed over to recover from each error
+    skipped: Vec<Span>,
+
+    // Comments are trivia, kept aside until the statement they precede claims them
+    pending_comments: Vec<String>,
+    unclaimed_comments: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<SpannedToken<'a>>) -> Self {
+        Parser::from_stream(tokens.into_iter().map(Ok))
+    }
+
+    // Parses tokens as they are produced, e.g. straight from a `Lexer`. Errors in the stream
+    // are reported alongside the parse errors
+    pub fn from_stream<I>(tokens: I) -> Self
+    where
+        I: Iterator<Item = Result<SpannedToken<'a>, Diagnostic>> + 'a,
+    {
+        let mut parser = Parser {
+            tokens: Box::new(tokens),
+            lookahead: VecDeque::new(),
+            previous: None,
+            position: 0,
+            errors: Vec::new(),
+            fail_fast: false,
+            skipped: Vec::new(),
+            pending_comments: Vec::new(),
+            unclaimed_comments: Vec::new(),
+        };
+        parser.fill(1);
+        parser
+    }
+
+    // Stop at the first error instead of recovering to report the rest
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    fn halted(&self) -> bool {
+        self.fail_fast && !self.errors.is_empty()
+    }
+
+    fn report(&mut self, e: Diagnostic) {
+        if !self.halted() {
+            self.errors.push(e);
+        }
+
+        // Everything after the first error reads as the end of the file
+        if self.halted() {
+            self.lookahead.clear();
+            self.fill(1);
         }
     }
 
-    fn peek_previous(&self) -> &Token {
-        if self.position > 0 {
-            match self.tokens.get(self.position - 1) {
-                Some(token) => token,
-                None => &Token::EOF
+    // Pulls tokens from the stream until `count` are buffered
+    fn fill(&mut self, count: usize) {
+        while self.lookahead.len() < count {
+            let next = if self.halted() { None } else { self.tokens.next() };
+
+            match next {
+                Some(Ok(token)) => match token.token {
+                    Token::Comment(text) => self.pending_comments.push(text.to_string()),
+                    _ => {
+                        let comments = std::mem::take(&mut self.pending_comments);
+                        self.lookahead.push_back(Buffered { token, comments });
+                    }
+                },
+                Some(Err(e)) => self.report(e),
+                None => {
+                    // Past the end of the stream everything reads as EOF
+                    let span = match self.lookahead.back().map(|buffered| &buffered.token).or(self.previous.as_ref()) {
+                        Some(token) => token.span,
+                        None => Span::default(),
+                    };
+                    let comments = std::mem::take(&mut self.pending_comments);
+                    self.lookahead.push_back(Buffered { token: SpannedToken { token: Token::EOF, span }, comments });
+                }
             }
         }
-        else {
-            &Token::EOF
+    }
+
+    // Takes every comment not yet attached to a statement, up to the current token
+    fn take_comments(&mut self) -> Vec<String> {
+        let mut taken = std::mem::take(&mut self.unclaimed_comments);
+        if let Some(current) = self.lookahead.front_mut() {
+            taken.append(&mut current.comments);
+        }
+
+        taken
+    }
+
+    fn current_token(&self) -> &Token<'a> {
+        match self.lookahead.front() {
+            Some(buffered) => &buffered.token.token,
+            None => &Token::EOF
+        }
+    }
+
+    // The token after the current one
+    fn peek(&mut self) -> &Token<'a> {
+        self.fill(2);
+        &self.lookahead[1].token.token
+    }
+
+    // `--` lexes as one token for `x--;`, but inside an expression it is a minus followed by a
+    // negation, as in `--y` or `a--1`
+    fn split_decrement(&mut self) {
+        let Some(current) = self.lookahead.front_mut() else {
+            return;
+        };
+        if current.token.token != Token::Decrement {
+            return;
         }
+
+        let span = current.token.span;
+        current.token = SpannedToken { token: Token::Minus, span: Span::new(span.start, span.start + 1, span.line, span.column) };
+
+        let second = SpannedToken { token: Token::Minus, span: Span::new(span.start + 1, span.end, span.line, span.column + 1) };
+        self.lookahead.insert(1, Buffered { token: second, comments: Vec::new() });
+    }
+
+    fn current_span(&self) -> Span {
+        match self.lookahead.front().map(|buffered| &buffered.token).or(self.previous.as_ref()) {
+            Some(token) => token.span,
+            None => Span::default()
+        }
+    }
+
+    fn previous_span(&self) -> Span {
+        match &self.previous {
+            Some(token) => token.span,
+            None => self.current_span()
+        }
+    }
+
+    // Span from a recorded start up to the end of the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span())
     }
 
     fn advance(&mut self) {
-        if self.position < self.tokens.len(){
-           self.position += 1;
+        if self.current_token() == &Token::EOF {
+            return;
+        }
+
+        if let Some(mut buffered) = self.lookahead.pop_front() {
+            self.unclaimed_comments.append(&mut buffered.comments);
+            self.previous = Some(buffered.token);
+            self.position += 1;
         }
+        self.fill(1);
     }
 
-    fn expect_token(&mut self, expected: &Token) -> Result<(), RosellaError> {
+    fn expect_token(&mut self, expected: &Token) -> Result<(), Diagnostic> {
         if self.current_token() == expected {
             self.advance();
             Ok(())
         }
         else {
-            Err(RosellaError::UnexpectedToken(expected.to_owned(), self.current_token().to_owned()))
+            let message = format!("Expected {}, found {}", expected, self.current_token());
+            let diagnostic = Diagnostic::error(codes::UNEXPECTED_TOKEN, message, self.current_span());
+
+            // A missing ';' is nearly always at the end of the previous token
+            if expected == &Token::Semicolon && self.position > 0 {
+                let previous = self.previous_span();
+                let insert_at = Span::new(previous.end, previous.end, previous.line, previous.column + (previous.end - previous.start));
+                Err(diagnostic
+                    .with_label(previous, "expected ';' after this")
+                    .with_suggestion(insert_at, "add ';' at the end of the statement", ";"))
+            }
+            else {
+                Err(diagnostic)
+            }
         }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, RosellaError> {
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+        let (statements, _, errors) = self.parse_partial();
+
+        if errors.is_empty() {
+            Ok(statements)
+        }
+        else {
+            Err(errors)
+        }
+    }
+
+    // Parses as much as it can, for tools that work on source with mistakes in it. Returns the
+    // statements that parsed, the source skipped over around the ones that didn't, and the errors
+    pub fn parse_partial(&mut self) -> (Vec<Stmt>, Vec<Span>, Vec<Diagnostic>) {
         let mut statements: Vec<Stmt> = Vec::new();
 
         while self.current_token() != &Token::EOF {
-            if self.current_token() == &Token::Comment {
-                self.advance();
-                continue; 
+            if let Some(stmt) = self.parse_stmt_or_recover() {
+                statements.push(stmt);
+            }
+        }
+
+        self.attach_trailing_comments(&mut statements);
+
+        (statements, std::mem::take(&mut self.skipped), std::mem::take(&mut self.errors))
+    }
+
+    // Parses a statement, recording any error and skipping ahead to the next statement boundary
+    fn parse_stmt_or_recover(&mut self) -> Option<Stmt> {
+        let start = self.position;
+        let start_span = self.current_span();
+
+        match self.parse_stmt() {
+            Ok(stmt) => Some(stmt),
+            Err(e) => {
+                self.report(e);
+                self.synchronise(start);
+                self.skipped.push(self.span_from(start_span));
+                None
+            }
+        }
+    }
+
+    fn synchronise(&mut self, start: usize) {
+        // Always make progress, otherwise the same token would fail forever
+        if self.position == start {
+            self.advance();
+        }
+
+        // Skip over any block that belongs to the broken statement
+        let mut depth = 0;
+
+        loop {
+            match self.current_token() {
+                Token::EOF => return,
+                Token::LBrace => depth += 1,
+                Token::RBrace if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                _ if depth > 0 => {}
+                Token::Semicolon => {
+                    self.advance();
+                    return;
+                }
+                Token::RBrace
+                | Token::Function | Token::Let | Token::If
+                | Token::With | Token::While | Token::For
+                | Token::Break | Token::Continue | Token::Return | Token::RawInstruction => return,
+                _ => {}
             }
+            self.advance();
+        }
+    }
 
-            statements.push(self.parse_stmt()?);
+    // Comments after the last statement of a block stay with that statement rather than leaking out of the block
+    fn attach_trailing_comments(&mut self, statements: &mut [Stmt]) {
+        if let Some(last) = statements.last_mut() {
+            let mut comments = self.take_comments();
+            last.trailing_comments.append(&mut comments);
         }
+    }
 
-        Ok(statements)
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, Diagnostic> {
+        self.expect_token(&Token::LBrace)?;
+
+        let mut body: Vec<Stmt> = Vec::new();
+        while self.current_token() != &Token::RBrace && self.current_token() != &Token::EOF {
+            if let Some(stmt) = self.parse_stmt_or_recover() {
+                body.push(stmt);
+            }
+        }
+
+        self.attach_trailing_comments(&mut body);
+
+        self.expect_token(&Token::RBrace)?;
+        Ok(body)
     }
 
-    fn parse_stmt(&mut self) -> Result<Stmt, RosellaError> {
-        match self.current_token() {
-            Token::Function => Ok(self.parse_fn_stmt()?),
-            Token::Let => Ok(self.parse_let_stmt()?),
-            Token::If => Ok(self.parse_if_stmt()?),
-            Token::With => Ok(self.parse_with_stmt()?),
-            Token::While => Ok(self.parse_while_stmt()?),
-            Token::RawInstruction => Ok(self.parse_raw_stmt()?),
+    fn parse_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.current_span();
+        let comments = self.take_comments();
+        let assignment = matches!(self.current_token(), Token::Identifier(_)) && is_assignment(self.peek());
+
+        let kind = match self.current_token() {
+            Token::Function => self.parse_fn_stmt()?,
+            Token::Let => self.parse_let_stmt()?,
+            Token::Identifier(_) if assignment => self.parse_assign_stmt()?,
+            Token::If => self.parse_if_stmt()?,
+            Token::With => self.parse_with_stmt()?,
+            Token::While => self.parse_while_stmt()?,
+            Token::For => self.parse_for_stmt()?,
+            Token::Break | Token::Continue => self.parse_loop_control_stmt()?,
+            Token::Return => self.parse_return_stmt()?,
+            Token::RawInstruction => self.parse_raw_stmt()?,
             _ => {
                 let expr = self.parse_expression()?;
-                Ok(Stmt::Expression(expr))
+                StmtKind::Expression(expr)
             }
-        }
+        };
+
+        let mut stmt = Stmt::new(kind, self.span_from(start));
+        stmt.comments = comments;
+        Ok(stmt)
     }
 
-    fn parse_fn_stmt(&mut self) -> Result<Stmt, RosellaError> {
+    fn parse_fn_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
         self.expect_token(&Token::Function)?;
 
         let name = self.parse_identifier("fn", "function name")?;
 
         self.expect_token(&Token::LParen)?;
-        let arguments = self.parse_arguments()?;
-        self.expect_token(&Token::LBrace)?;
-
-        let mut body: Vec<Stmt> = Vec::new();
+        let parameters = self.parse_parameters()?;
 
-        while self.current_token() != &Token::RBrace {
-            body.push(self.parse_stmt()?);
-        }
+        let return_type = if self.current_token() == &Token::Arrow {
+            self.advance();
+            Some(self.parse_identifier("->", "return type")?)
+        } else {
+            None
+        };
 
-        self.expect_token(&Token::RBrace)?;
+        let body = self.parse_block()?;
 
-        Ok(Stmt::Function {
-            name,
-            arguments: if arguments.is_empty() { None } else { Some(arguments) },
-            body,
-        })
+        Ok(StmtKind::Function { name, parameters, return_type, body })
     }
 
-    fn parse_let_stmt(&mut self) -> Result<Stmt, RosellaError> {
+    fn parse_let_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
         self.expect_token(&Token::Let)?;
 
         let variable_type = self.parse_identifier("let", "variable type")?;
@@ -177,87 +578,139 @@ impl Parser {
         self.expect_token(&Token::Assign)?;
         let value = self.parse_expression()?;
         self.expect_token(&Token::Semicolon)?;
-        Ok(Stmt::Let { variable_type, name, value })
+        Ok(StmtKind::Let { variable_type, name, value })
+    }
+
+    fn parse_assign_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
+        let name = self.parse_identifier("assignment", "variable name")?;
+
+        let operator = match self.current_token() {
+            Token::Assign => AssignOp::Set,
+            Token::PlusAssign => AssignOp::Add,
+            Token::MinusAssign => AssignOp::Subtract,
+            Token::MultiplyAssign => AssignOp::Multiply,
+            Token::DivideAssign => AssignOp::Divide,
+            Token::Increment => AssignOp::Increment,
+            Token::Decrement => AssignOp::Decrement,
+            _ => return Err(Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("Expected an assignment operator, found {}", self.current_token()), self.current_span())),
+        };
+        self.advance();
+
+        let value = match operator {
+            AssignOp::Increment | AssignOp::Decrement => None,
+            _ => Some(self.parse_expression()?),
+        };
+        self.expect_token(&Token::Semicolon)?;
+
+        Ok(StmtKind::Assign { name, operator, value })
     }
 
-    fn parse_if_stmt(&mut self) -> Result<Stmt, RosellaError> {
+    fn parse_if_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
         self.expect_token(&Token::If)?;
 
-        let condition_type = self.parse_identifier("if", "for comparion type")?;
+        let condition_type = self.parse_identifier("if", "comparison type")?;
 
         self.expect_token(&Token::LParen)?;
         let condition = self.parse_expression()?;
         self.expect_token(&Token::RParen)?;
 
-        self.expect_token(&Token::LBrace)?;
-
-        let mut then_branch: Vec<Stmt> = Vec::new();
-        while self.current_token() != &Token::RBrace {
-            then_branch.push(self.parse_stmt()?);
-        }
-        self.expect_token(&Token::RBrace)?;
+        let then_branch = self.parse_block()?;
 
         let else_branch = if self.current_token() == &Token::Else {
             self.advance();
             if self.current_token() == &Token::If {
-                Some(vec![self.parse_if_stmt()?])
+                let start = self.current_span();
+                let else_if = self.parse_if_stmt()?;
+                Some(vec![Stmt::new(else_if, self.span_from(start))])
             } else {
-                self.expect_token(&Token::LBrace)?;
-                let mut else_branch: Vec<Stmt> = Vec::new();
-                while self.current_token() != &Token::RBrace {
-                    else_branch.push(self.parse_stmt()?);
-                }
-                self.expect_token(&Token::RBrace)?;
-                Some(else_branch)
+                Some(self.parse_block()?)
             }
         } else {
             None
         };
 
-        Ok(Stmt::If { condition_type, condition, then_branch, else_branch })
+        Ok(StmtKind::If { condition_type, condition, then_branch, else_branch })
     }
 
-    fn parse_with_stmt(&mut self) -> Result<Stmt, RosellaError> {
+    fn parse_with_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
         self.expect_token(&Token::With)?;
 
+        let os_span = self.current_span();
         let os = match self.parse_identifier("with", "OS type")?.as_str() {
             "windows" => OS::Windows,
             "linux" => OS::Linux,
-            _ => return Err(RosellaError::ParseError("Invalid OS type in 'with' statement".to_string())),
+            _ => return Err(Diagnostic::error(codes::INVALID_OS, "Invalid OS type in 'with' statement", os_span)
+                .with_help("expected 'windows' or 'linux'")),
         };
 
-        self.expect_token(&Token::LBrace)?;
-
-        let mut body: Vec<Stmt> = Vec::new();
-        while self.current_token() != &Token::RBrace {
-            body.push(self.parse_stmt()?);
-        }
-        self.expect_token(&Token::RBrace)?;
+        let body = self.parse_block()?;
 
-        Ok(Stmt::With { os, body })
+        Ok(StmtKind::With { os, body })
     }
 
-    fn parse_while_stmt(&mut self) -> Result<Stmt, RosellaError> {
+    fn parse_while_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
         self.expect_token(&Token::While)?;
 
-        let condition_type = self.parse_identifier("while", "for comparison type")?;
+        let condition_type = self.parse_identifier("while", "comparison type")?;
 
         self.expect_token(&Token::LParen)?;
         let condition = self.parse_expression()?;
         self.expect_token(&Token::RParen)?;
 
-        self.expect_token(&Token::LBrace)?;
+        let body = self.parse_block()?;
 
-        let mut body: Vec<Stmt> = Vec::new();
-        while self.current_token() != &Token::RBrace {
-            body.push(self.parse_stmt()?);
-        }
-        self.expect_token(&Token::RBrace)?;
+        Ok(StmtKind::While { condition_type, condition, body })
+    }
+
+    fn parse_for_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.expect_token(&Token::For)?;
+
+        let variable = self.parse_identifier("for", "loop variable")?;
+        self.expect_token(&Token::In)?;
 
-        Ok(Stmt::While { condition_type, condition, body })
+        let start = self.parse_expression()?;
+        self.expect_token(&Token::DotDot)?;
+        let end = self.parse_expression()?;
+
+        // 'step' is only special here, so it can still be used as a variable name
+        let step = match self.current_token() {
+            Token::Identifier(word) if word.as_str() == "step" => {
+                self.advance();
+                Some(self.parse_expression()?)
+            }
+            _ => None,
+        };
+
+        let body = self.parse_block()?;
+
+        Ok(StmtKind::For { variable, start, end, step, body })
     }
 
-    fn parse_raw_stmt(&mut self) -> Result<Stmt, RosellaError> {
+    // 'break;' or 'continue;'
+    fn parse_loop_control_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
+        let kind = match self.current_token() {
+            Token::Break => StmtKind::Break,
+            _ => StmtKind::Continue,
+        };
+        self.advance();
+        self.expect_token(&Token::Semicolon)?;
+
+        Ok(kind)
+    }
+
+    fn parse_return_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.expect_token(&Token::Return)?;
+
+        let value = match self.current_token() {
+            Token::Semicolon => None,
+            _ => Some(self.parse_expression()?),
+        };
+        self.expect_token(&Token::Semicolon)?;
+
+        Ok(StmtKind::Return(value))
+    }
+
+    fn parse_raw_stmt(&mut self) -> Result<StmtKind, Diagnostic> {
         self.expect_token(&Token::RawInstruction)?;
 
         let mut instructions: Vec<Expr> = Vec::new();
@@ -268,108 +721,175 @@ impl Parser {
 
         self.expect_token(&Token::Semicolon)?;
 
-        Ok(Stmt::RawInstruction(instructions))
+        Ok(StmtKind::RawInstruction(instructions))
     }
 
-    fn parse_expression(&mut self) -> Result<Expr, RosellaError> {
-        self.binary_expression(&[
-            &[Token::Equal, Token::NotEqual],
-            &[Token::GreaterThan, Token::GreaterThanEq, Token::LessThan, Token::LessThanEq],
-            &[Token::Plus, Token::Minus],
-            &[Token::Multiply, Token::Divide],
-        ], 0)
+    fn parse_expression(&mut self) -> Result<Expr, Diagnostic> {
+        self.expression_with_binding_power(0)
     }
 
-    fn binary_expression(&mut self, precedence: &[&[Token]], level: usize) -> Result<Expr, RosellaError> {
-        if level >= precedence.len() {
-            return self.primary();
-        }
-
-        let mut expr = self.binary_expression(precedence, level + 1)?;
-        let current_operators = precedence[level];
+    // Pratt parser: keeps folding operators into the expression while they bind at least as tightly as `min_power`
+    fn expression_with_binding_power(&mut self, min_power: u8) -> Result<Expr, Diagnostic> {
+        let start = self.current_span();
+        let mut expr = self.prefix()?;
 
         loop {
-            if current_operators.contains(self.current_token()) {
-                self.advance();
-                let operator = self.token_to_binary_op(self.peek_previous().clone())?;
+            self.split_decrement();
 
-                let right = self.binary_expression(precedence, level + 1)?;
+            if let Some(power) = postfix_binding_power(self.current_token()) {
+                if power < min_power {
+                    break;
+                }
 
-                expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) }
+                expr = self.postfix(expr, start)?;
+                continue;
             }
-            else {
+
+            let Some((operator, left_power, right_power)) = infix_binding_power(self.current_token()) else {
+                break;
+            };
+            if left_power < min_power {
                 break;
             }
+
+            self.advance();
+            let right = self.expression_with_binding_power(right_power)?;
+
+            expr = Expr::new(ExprKind::Binary { left: Box::new(expr), operator, right: Box::new(right) }, self.span_from(start));
         }
 
         Ok(expr)
     }
 
-    fn token_to_binary_op(&self, token: Token) -> Result<BinaryOp, RosellaError> {
-        match token {
-            Token::Equal => Ok(BinaryOp::Equal),
-            Token::NotEqual => Ok(BinaryOp::NotEqual),
-            Token::GreaterThan => Ok(BinaryOp::GreaterThan),
-            Token::GreaterThanEq => Ok(BinaryOp::GreaterThanEq),
-            Token::LessThan => Ok(BinaryOp::LessThan),
-            Token::LessThanEq => Ok(BinaryOp::LessThanEq),
-            Token::Plus => Ok(BinaryOp::Add),
-            Token::Minus => Ok(BinaryOp::Subtract),
-            Token::Multiply => Ok(BinaryOp::Multiply),
-            Token::Divide => Ok(BinaryOp::Divide),
-            _ => Err(RosellaError::ParseError(format!("{:?} is not a valid binary operator", token)))
+    fn prefix(&mut self) -> Result<Expr, Diagnostic> {
+        self.split_decrement();
+        let start = self.current_span();
+
+        let operator = match self.current_token() {
+            Token::Not => UnaryOp::Not,
+            Token::Minus => UnaryOp::Negate,
+            _ => return self.primary(),
+        };
+        self.advance();
+
+        let operand = self.expression_with_binding_power(PREFIX_BINDING_POWER)?;
+
+        // Negative literals stay plain numbers
+        if operator == UnaryOp::Negate {
+            match operand.kind {
+                ExprKind::Int(n) => return Ok(Expr::new(ExprKind::Int(-n), self.span_from(start))),
+                ExprKind::Float(n) => return Ok(Expr::new(ExprKind::Float(-n), self.span_from(start))),
+                _ => {}
+            }
+        }
+
+        Ok(Expr::new(ExprKind::Unary { operator, operand: Box::new(operand) }, self.span_from(start)))
+    }
+
+    fn postfix(&mut self, target: Expr, start: Span) -> Result<Expr, Diagnostic> {
+        match self.current_token() {
+            Token::LParen => {
+                let name = match target.kind {
+                    ExprKind::Identifier(name) => name,
+                    _ => return Err(Diagnostic::error(codes::INVALID_CALL_TARGET, format!("Cannot call {}", target.kind.description()), target.span)
+                        .with_help("only functions can be called, by writing their name before '('")),
+                };
+
+                self.advance();
+                let args = self.parse_arguments()?;
+
+                Ok(Expr::new(ExprKind::Call { name, args }, self.span_from(start)))
+            }
+            Token::LBraceSquare => {
+                self.advance();
+                let index = self.parse_expression()?;
+                self.expect_token(&Token::RBraceSquare)?;
+
+                Ok(Expr::new(ExprKind::Index { target: Box::new(target), index: Box::new(index) }, self.span_from(start)))
+            }
+            _ => Ok(target),
         }
     }
 
-    fn primary(&mut self) -> Result<Expr, RosellaError> {
-        let primary = match self.current_token() {
-            Token::Number(n) => {
+    fn primary(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.current_span();
+
+        match self.current_token() {
+            Token::Int(n) => {
+                let num = *n;
+                self.advance();
+                Ok(Expr::new(ExprKind::Int(num), start))
+            }
+            Token::Float(n) => {
                 let num = *n;
                 self.advance();
-                Ok(Expr::Number(num))
+                Ok(Expr::new(ExprKind::Float(num), start))
+            }
+            Token::True | Token::False => {
+                let value = self.current_token() == &Token::True;
+                self.advance();
+                Ok(Expr::new(ExprKind::Boolean(value), start))
             }
             Token::String(s) => {
-                let string = s.clone();
+                let string = s.to_string();
+                self.advance();
+                Ok(Expr::new(ExprKind::String(string), start))
+            }
+            Token::RawString(s) => {
+                let string = s.to_string();
+                self.advance();
+                Ok(Expr::new(ExprKind::RawString(string), start))
+            }
+            Token::InterpolatedString(parts) => {
+                let parts = parts.clone();
                 self.advance();
-                Ok(Expr::String(string))
+                Ok(Expr::new(ExprKind::Interpolated(self.parse_interpolated(parts, start)?), start))
             }
             Token::Identifier(name) => {
-                let variable_name = name.clone();
+                let variable_name = name.to_string();
                 self.advance();
-                Ok(Expr::Identifier(variable_name))
+                Ok(Expr::new(ExprKind::Identifier(variable_name), start))
             }
             Token::LParen => {
                 self.advance();
                 let expr = self.parse_expression()?;
                 self.expect_token(&Token::RParen)?;
-                Ok(expr)
+                Ok(Expr::new(expr.kind, self.span_from(start)))
             }
-            _ => Err(RosellaError::ParseError(format!("Unexpected token: {:?}", self.current_token())))
-        };
-
-        if let Token::Identifier(_) = self.peek_previous() {
-            if let Token::LParen = self.current_token() {
-                let name = match self.peek_previous() {
-                    Token::Identifier(name) => name.clone(),
-                    _ => return Err(RosellaError::ParseError("Expected identifer after 'fn'".to_string())),
-                };
-
-                self.advance();
+            _ => Err(Diagnostic::error(codes::EXPECTED_EXPRESSION, format!("Expected an expression, found {}", self.current_token()), start))
+        }
+    }
 
-                let args = self.parse_arguments()?;
-                
-                Ok(Expr::Call { name, args })
-            }
-            else {
-                primary
+    // Parses the code of each `${...}` in a string with its own parser
+    fn parse_interpolated(&self, parts: Vec<StringPart<'a>>, string_span: Span) -> Result<Vec<InterpolatedPart>, Diagnostic> {
+        let mut interpolated = Vec::new();
+
+        for part in parts {
+            match part {
+                StringPart::Literal(text) => interpolated.push(InterpolatedPart::Literal(text.into_owned())),
+                StringPart::Code(mut tokens) => {
+                    let end = match tokens.last() {
+                        Some(token) => token.span,
+                        None => return Err(Diagnostic::error(codes::EXPECTED_EXPRESSION, "Expected an expression inside '${}'", string_span)
+                            .with_help("put a variable name between the braces, e.g. ${name}")),
+                    };
+                    tokens.push(SpannedToken { token: Token::EOF, span: Span::new(end.end, end.end, end.line, end.column + (end.end - end.start)) });
+
+                    let mut parser = Parser::new(tokens);
+                    let expr = parser.parse_expression()?;
+                    if parser.current_token() != &Token::EOF {
+                        return Err(Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("Expected '}}' to end the interpolation, found {}", parser.current_token()), parser.current_span()));
+                    }
+
+                    interpolated.push(InterpolatedPart::Expr(expr));
+                }
             }
         }
-        else {
-            primary
-        }
+
+        Ok(interpolated)
     }
 
-    fn parse_arguments(&mut self) -> Result<Vec<Expr>, RosellaError> {
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, Diagnostic> {
         let mut arguments = Vec::new();
 
         if self.current_token() == &Token::RParen {
@@ -381,27 +901,286 @@ impl Parser {
             arguments.push(self.parse_expression()?);
 
             match self.current_token() {
-                &Token::Comma => {
+                Token::Comma => {
                     self.advance();
                 },
-                &Token::RParen => {
+                Token::RParen => {
                     self.advance();
                     break;
                 },
-                _ => return Err(RosellaError::ParseError("Expected ',' or ')' after argument".to_string()))
+                _ => return Err(Diagnostic::error(codes::INVALID_ARGUMENT_LIST, format!("Expected ',' or ')' after argument, found {}", self.current_token()), self.current_span()))
             }
         }
 
         Ok(arguments)
     }
 
-    fn parse_identifier(&mut self, context: &str, reason: &str) -> Result<String, RosellaError> {
+    fn parse_parameters(&mut self) -> Result<Vec<Param>, Diagnostic> {
+        let mut parameters = Vec::new();
+
+        if self.current_token() == &Token::RParen {
+            self.advance();
+            return Ok(parameters);
+        }
+
+        loop {
+            parameters.push(self.parse_parameter()?);
+
+            match self.current_token() {
+                Token::Comma => {
+                    self.advance();
+                },
+                Token::RParen => {
+                    self.advance();
+                    break;
+                },
+                _ => return Err(Diagnostic::error(codes::INVALID_ARGUMENT_LIST, format!("Expected ',' or ')' after parameter, found {}", self.current_token()), self.current_span()))
+            }
+        }
+
+        Ok(parameters)
+    }
+
+    // Either `type name` or just `name`
+    fn parse_parameter(&mut self) -> Result<Param, Diagnostic> {
+        let start = self.current_span();
+
+        if !matches!(self.current_token(), Token::Identifier(_)) {
+            return Err(Diagnostic::error(codes::EXPECTED_PARAMETER, format!("Expected a parameter name, found {}", self.current_token()), start)
+                .with_help("parameters are written as a type and a name, e.g. int count"));
+        }
+        let first = self.parse_identifier("(", "parameter")?;
+
+        let (param_type, name) = match self.current_token() {
+            Token::Identifier(_) => (Some(first), self.parse_identifier("parameter type", "parameter name")?),
+            _ => (None, first),
+        };
+
+        Ok(Param { param_type, name, span: self.span_from(start) })
+    }
+
+    fn parse_identifier(&mut self, context: &str, reason: &str) -> Result<String, Diagnostic> {
         let value = match self.current_token() {
-            Token::Identifier(value) => Ok(value.clone()),
-            _ => return Err(RosellaError::ParseError(format!("Expected identifer ({}) after '{}'", reason, context))),
+            Token::Identifier(value) => Ok(value.to_string()),
+            _ => return Err(Diagnostic::error(
+                codes::EXPECTED_IDENTIFIER,
+                format!("Expected identifier ({}) after '{}', found {}", reason, context, self.current_token()),
+                self.current_span())),
         };
         self.advance();
 
         value
     }
-}
\ No newline at end of file
+}
+
+fn is_assignment(token: &Token) -> bool {
+    matches!(token,
+        Token::Assign | Token::PlusAssign | Token::MinusAssign
+        | Token::MultiplyAssign | Token::DivideAssign
+        | Token::Increment | Token::Decrement)
+}
+
+// Binding powers of the infix operators, loosest first. Each operator binds a little tighter on its
+// right so that it is left-associative; a right-associative operator would bind tighter on its left
+fn infix_binding_power(token: &Token) -> Option<(BinaryOp, u8, u8)> {
+    let (operator, left, right) = match token {
+        Token::Or => (BinaryOp::Or, 1, 2),
+        Token::And => (BinaryOp::And, 3, 4),
+        Token::Equal => (BinaryOp::Equal, 5, 6),
+        Token::NotEqual => (BinaryOp::NotEqual, 5, 6),
+        Token::LessThan => (BinaryOp::LessThan, 7, 8),
+        Token::LessThanEq => (BinaryOp::LessThanEq, 7, 8),
+        Token::GreaterThan => (BinaryOp::GreaterThan, 7, 8),
+        Token::GreaterThanEq => (BinaryOp::GreaterThanEq, 7, 8),
+        Token::Plus => (BinaryOp::Add, 9, 10),
+        Token::Minus => (BinaryOp::Subtract, 9, 10),
+        Token::Multiply => (BinaryOp::Multiply, 11, 12),
+        Token::Divide => (BinaryOp::Divide, 11, 12),
+        Token::Modulo => (BinaryOp::Modulo, 11, 12),
+        _ => return None,
+    };
+
+    Some((operator, left, right))
+}
+
+// `!` and unary `-` bind tighter than any infix operator
+const PREFIX_BINDING_POWER: u8 = 13;
+
+// Calls and indexing bind tightest of all, so `-a[0]` negates the element
+fn postfix_binding_power(token: &Token) -> Option<u8> {
+    match token {
+        Token::LParen | Token::LBraceSquare => Some(15),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(source: &str) -> Result<Expr, Diagnostic> {
+        let tokens = Lexer::new(source).tokenise().expect("source should lex");
+        let mut parser = Parser::new(tokens);
+        let expr = parser.parse_expression()?;
+        assert_eq!(parser.current_token(), &Token::EOF, "trailing tokens after {:?}", source);
+        Ok(expr)
+    }
+
+    // Writes the tree with explicit grouping, e.g. `1 + 2 * 3` as `(+ 1 (* 2 3))`
+    fn grouped(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Int(n) => n.to_string(),
+            ExprKind::Float(n) => n.to_string(),
+            ExprKind::Boolean(value) => value.to_string(),
+            ExprKind::String(s) | ExprKind::RawString(s) => format!("{:?}", s),
+            ExprKind::Interpolated(_) => "interpolated".to_string(),
+            ExprKind::Identifier(name) => name.clone(),
+            ExprKind::Binary { left, operator, right } => {
+                let operator = match operator {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Subtract => "-",
+                    BinaryOp::Multiply => "*",
+                    BinaryOp::Divide => "/",
+                    BinaryOp::Modulo => "%",
+                    BinaryOp::Equal => "==",
+                    BinaryOp::NotEqual => "!=",
+                    BinaryOp::LessThan => "<",
+                    BinaryOp::LessThanEq => "<=",
+                    BinaryOp::GreaterThan => ">",
+                    BinaryOp::GreaterThanEq => ">=",
+                    BinaryOp::And => "&&",
+                    BinaryOp::Or => "||",
+                };
+                format!("({} {} {})", operator, grouped(left), grouped(right))
+            }
+            ExprKind::Unary { operator: UnaryOp::Not, operand } => format!("(! {})", grouped(operand)),
+            ExprKind::Unary { operator: UnaryOp::Negate, operand } => format!("(neg {})", grouped(operand)),
+            ExprKind::Call { name, args } => {
+                let args: Vec<String> = args.iter().map(grouped).collect();
+                format!("(call {} [{}])", name, args.join(" "))
+            }
+            ExprKind::Index { target, index } => format!("(index {} {})", grouped(target), grouped(index)),
+        }
+    }
+
+    fn assert_parses(source: &str, expected: &str) {
+        let expr = parse(source).unwrap_or_else(|e| panic!("{:?} failed to parse: {}", source, e));
+        assert_eq!(grouped(&expr), expected, "parsing {:?}", source);
+    }
+
+    #[test]
+    fn multiplicative_binds_tighter_than_additive() {
+        assert_parses("1 + 2 * 3", "(+ 1 (* 2 3))");
+        assert_parses("1 * 2 + 3", "(+ (* 1 2) 3)");
+        assert_parses("a - b / c", "(- a (/ b c))");
+        assert_parses("a + b % c", "(+ a (% b c))");
+    }
+
+    #[test]
+    fn operators_of_equal_precedence_are_left_associative() {
+        assert_parses("1 - 2 - 3", "(- (- 1 2) 3)");
+        assert_parses("8 / 4 / 2", "(/ (/ 8 4) 2)");
+        assert_parses("a % b * c", "(* (% a b) c)");
+        assert_parses("a + b - c + d", "(+ (- (+ a b) c) d)");
+        assert_parses("a == b != c", "(!= (== a b) c)");
+        assert_parses("a || b || c", "(|| (|| a b) c)");
+        assert_parses("a && b && c", "(&& (&& a b) c)");
+    }
+
+    #[test]
+    fn comparisons_bind_looser_than_arithmetic() {
+        assert_parses("a + 1 < b * 2", "(< (+ a 1) (* b 2))");
+        assert_parses("x % 2 >= 1", "(>= (% x 2) 1)");
+    }
+
+    #[test]
+    fn equality_binds_looser_than_ordering() {
+        assert_parses("a < b == c > d", "(== (< a b) (> c d))");
+        assert_parses("a <= b != c >= d", "(!= (<= a b) (>= c d))");
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_parses("a || b && c", "(|| a (&& b c))");
+        assert_parses("a && b || c", "(|| (&& a b) c)");
+        assert_parses("a == 1 && b < 2 || c", "(|| (&& (== a 1) (< b 2)) c)");
+    }
+
+    #[test]
+    fn prefix_operators_bind_tighter_than_infix() {
+        assert_parses("-a * b", "(* (neg a) b)");
+        assert_parses("!a && b", "(&& (! a) b)");
+        assert_parses("!a == b", "(== (! a) b)");
+        assert_parses("a - -b", "(- a (neg b))");
+        assert_parses("!!a", "(! (! a))");
+        assert_parses("- -a", "(neg (neg a))");
+    }
+
+    #[test]
+    fn double_minus_in_an_expression_is_two_minuses() {
+        assert_parses("--a", "(neg (neg a))");
+        assert_parses("--5", "5");
+        assert_parses("a--b", "(- a (neg b))");
+        assert_parses("a--1 * b", "(- a (* -1 b))");
+    }
+
+    #[test]
+    fn negative_literals_are_folded() {
+        assert_parses("-1", "-1");
+        assert_parses("-1.5", "-1.5");
+        assert_parses("2 * -3", "(* 2 -3)");
+        assert_parses("-(1)", "-1");
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_parses("(1 + 2) * 3", "(* (+ 1 2) 3)");
+        assert_parses("a - (b - c)", "(- a (- b c))");
+        assert_parses("!(a && b)", "(! (&& a b))");
+        assert_parses("-(a + b)", "(neg (+ a b))");
+    }
+
+    #[test]
+    fn calls_bind_tighter_than_operators() {
+        assert_parses("f(1, 2) + g()", "(+ (call f [1 2]) (call g []))");
+        assert_parses("!exists(p)", "(! (call exists [p]))");
+        assert_parses("-f(x) * 2", "(* (neg (call f [x])) 2)");
+        assert_parses("f(a + b, c * d)", "(call f [(+ a b) (* c d)])");
+        assert_parses("f(g(x))", "(call f [(call g [x])])");
+    }
+
+    #[test]
+    fn indexing_binds_tighter_than_prefix_operators() {
+        assert_parses("a[0]", "(index a 0)");
+        assert_parses("-a[0]", "(neg (index a 0))");
+        assert_parses("a[i][j]", "(index (index a i) j)");
+        assert_parses("a[i + 1] * 2", "(* (index a (+ i 1)) 2)");
+        assert_parses("f(x)[0]", "(index (call f [x]) 0)");
+    }
+
+    #[test]
+    fn only_names_can_be_called() {
+        let error = parse("f()(1)").unwrap_err();
+        assert_eq!(error.code, codes::INVALID_CALL_TARGET);
+
+        let error = parse("\"text\"(1)").unwrap_err();
+        assert_eq!(error.code, codes::INVALID_CALL_TARGET);
+    }
+
+    #[test]
+    fn incomplete_expressions_are_errors() {
+        assert_eq!(parse("1 +").unwrap_err().code, codes::EXPECTED_EXPRESSION);
+        assert_eq!(parse("a[0").unwrap_err().code, codes::UNEXPECTED_TOKEN);
+        assert_eq!(parse("(1 + 2").unwrap_err().code, codes::UNEXPECTED_TOKEN);
+    }
+
+    #[test]
+    fn binary_spans_cover_both_operands() {
+        let expr = parse("a + b * c").unwrap();
+        assert_eq!((expr.span.start, expr.span.end), (0, 9));
+
+        let ExprKind::Binary { right, .. } = expr.kind else { panic!("expected a binary expression") };
+        assert_eq!((right.span.start, right.span.end), (4, 9));
+    }
+}
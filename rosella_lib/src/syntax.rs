@@ -0,0 +1,472 @@
+use std::fmt;
+
+use super::lexer::{Lexer, Token};
+use super::parser::{Expr, ExprKind, Parser, Stmt, StmtKind};
+use super::diagnostic::Diagnostic;
+use super::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Root,
+
+    // Statements
+    ExpressionStmt,
+    Let,
+    Assign,
+    If,
+    With,
+    While,
+    For,
+    Function,
+    Param,
+    Break,
+    Continue,
+    Return,
+    RawInstruction,
+    Error,                  // Source the parser skipped over after an error
+
+    // Expressions
+    Int,
+    Float,
+    Boolean,
+    String,
+    RawString,
+    Interpolated,
+    Identifier,
+    Binary,
+    Unary,
+    Call,
+    Index,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+    Skipped,                // Characters the lexer rejected
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken<'a> {
+    pub token: Token<'a>,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(SyntaxToken<'a>),
+    Trivia(Trivia<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode<'a> {
+    pub kind: NodeKind,
+    pub span: Span,
+    pub children: Vec<SyntaxElement<'a>>,
+}
+
+impl<'a> SyntaxNode<'a> {
+    // Every token under this node in source order, without trivia
+    pub fn tokens(&self) -> Vec<&SyntaxToken<'a>> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'n>(&'n self, tokens: &mut Vec<&'n SyntaxToken<'a>>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+                SyntaxElement::Trivia(_) => {}
+            }
+        }
+    }
+}
+
+impl fmt::Display for SyntaxNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.children.iter().try_for_each(|child| write!(f, "{}", child))
+    }
+}
+
+impl fmt::Display for SyntaxElement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxElement::Node(node) => write!(f, "{}", node),
+            SyntaxElement::Token(token) => f.write_str(token.text),
+            SyntaxElement::Trivia(trivia) => f.write_str(trivia.text),
+        }
+    }
+}
+
+// Lossless view of a source file: every token, comment and run of whitespace, grouped under the
+// statements and expressions of the AST. Printing it gives back the source byte for byte
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree<'a> {
+    root: SyntaxNode<'a>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    // Builds the tree around statements already parsed from `source`. Passing no statements
+    // still gives a tree that round-trips, with every token directly under the root
+    pub fn new(source: &'a str, statements: &[Stmt]) -> Self {
+        SyntaxTree::with_errors(source, statements, &[])
+    }
+
+    // As `new`, with the source the parser skipped over to recover from errors wrapped in error nodes
+    pub fn with_errors(source: &'a str, statements: &[Stmt], skipped: &[Span]) -> Self {
+        let mut extents: Vec<Extent> = skipped.iter().map(|span| Extent { kind: NodeKind::Error, span: *span }).collect();
+        statements.iter().for_each(|stmt| collect_stmt(stmt, &mut extents));
+
+        // Enclosing nodes come before the nodes inside them
+        extents.sort_by_key(|extent| (extent.span.start, std::cmp::Reverse(extent.span.end)));
+
+        let mut builder = Builder::new(source, extents);
+        for result in Lexer::new(source) {
+            // Lexing recovers from its errors, so the text it rejected is picked up as skipped trivia
+            let Ok(token) = result else { continue };
+
+            builder.gap(token.span.start);
+            match token.token {
+                Token::EOF => break,
+                Token::Comment(_) => builder.trivia(TriviaKind::Comment, token.span.end),
+                token_kind => builder.token(token_kind, token.span.end),
+            }
+        }
+        builder.gap(source.len());
+
+        SyntaxTree { root: builder.finish() }
+    }
+
+    // Parses `source` and builds its tree, along with any errors found on the way. The statements
+    // that parsed keep their nodes, so only the parts with mistakes are left as error nodes
+    pub fn parse(source: &'a str) -> (Self, Vec<Diagnostic>) {
+        let (statements, skipped, errors) = Parser::from_stream(Lexer::new(source)).parse_partial();
+        (SyntaxTree::with_errors(source, &statements, &skipped), errors)
+    }
+
+    pub fn root(&self) -> &SyntaxNode<'a> {
+        &self.root
+    }
+}
+
+impl fmt::Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root)
+    }
+}
+
+// Where an AST node sits in the source
+struct Extent {
+    kind: NodeKind,
+    span: Span,
+}
+
+fn collect_stmt(stmt: &Stmt, extents: &mut Vec<Extent>) {
+    let kind = match &stmt.kind {
+        StmtKind::Expression(_) => NodeKind::ExpressionStmt,
+        StmtKind::Let { .. } => NodeKind::Let,
+        StmtKind::Assign { .. } => NodeKind::Assign,
+        StmtKind::If { .. } => NodeKind::If,
+        StmtKind::With { .. } => NodeKind::With,
+        StmtKind::While { .. } => NodeKind::While,
+        StmtKind::For { .. } => NodeKind::For,
+        StmtKind::Function { .. } => NodeKind::Function,
+        StmtKind::Break => NodeKind::Break,
+        StmtKind::Continue => NodeKind::Continue,
+        StmtKind::Return(_) => NodeKind::Return,
+        StmtKind::RawInstruction(_) => NodeKind::RawInstruction,
+    };
+    extents.push(Extent { kind, span: stmt.span });
+
+    match &stmt.kind {
+        StmtKind::Expression(expr) => collect_expr(expr, extents),
+        StmtKind::Let { value, .. } => collect_expr(value, extents),
+        StmtKind::Assign { value, .. } => value.iter().for_each(|expr| collect_expr(expr, extents)),
+        StmtKind::If { condition, then_branch, else_branch, .. } => {
+            collect_expr(condition, extents);
+            then_branch.iter().chain(else_branch.iter().flatten()).for_each(|stmt| collect_stmt(stmt, extents));
+        }
+        StmtKind::With { body, .. } => body.iter().for_each(|stmt| collect_stmt(stmt, extents)),
+        StmtKind::While { condition, body, .. } => {
+            collect_expr(condition, extents);
+            body.iter().for_each(|stmt| collect_stmt(stmt, extents));
+        }
+        StmtKind::For { start, end, step, body, .. } => {
+            collect_expr(start, extents);
+            collect_expr(end, extents);
+            step.iter().for_each(|expr| collect_expr(expr, extents));
+            body.iter().for_each(|stmt| collect_stmt(stmt, extents));
+        }
+        StmtKind::Function { parameters, body, .. } => {
+            parameters.iter().for_each(|param| extents.push(Extent { kind: NodeKind::Param, span: param.span }));
+            body.iter().for_each(|stmt| collect_stmt(stmt, extents));
+        }
+        StmtKind::Break | StmtKind::Continue => {}
+        StmtKind::Return(value) => value.iter().for_each(|expr| collect_expr(expr, extents)),
+        StmtKind::RawInstruction(exprs) => exprs.iter().for_each(|expr| collect_expr(expr, extents)),
+    }
+}
+
+fn collect_expr(expr: &Expr, extents: &mut Vec<Extent>) {
+    let kind = match &expr.kind {
+        ExprKind::Int(_) => NodeKind::Int,
+        ExprKind::Float(_) => NodeKind::Float,
+        ExprKind::Boolean(_) => NodeKind::Boolean,
+        ExprKind::String(_) => NodeKind::String,
+        ExprKind::RawString(_) => NodeKind::RawString,
+        ExprKind::Interpolated(_) => NodeKind::Interpolated,
+        ExprKind::Identifier(_) => NodeKind::Identifier,
+        ExprKind::Binary { .. } => NodeKind::Binary,
+        ExprKind::Unary { .. } => NodeKind::Unary,
+        ExprKind::Call { .. } => NodeKind::Call,
+        ExprKind::Index { .. } => NodeKind::Index,
+    };
+    extents.push(Extent { kind, span: expr.span });
+
+    // The code inside `${...}` is part of its string token, so it gets no nodes of its own
+    match &expr.kind {
+        ExprKind::Binary { left, right, .. } => {
+            collect_expr(left, extents);
+            collect_expr(right, extents);
+        }
+        ExprKind::Unary { operand, .. } => collect_expr(operand, extents),
+        ExprKind::Call { args, .. } => args.iter().for_each(|arg| collect_expr(arg, extents)),
+        ExprKind::Index { target, index } => {
+            collect_expr(target, extents);
+            collect_expr(index, extents);
+        }
+        _ => {}
+    }
+}
+
+// Walks the source front to back, opening a node when its first token is reached
+// and closing it once everything up to its end has been added
+struct Builder<'a> {
+    source: &'a str,
+    extents: std::iter::Peekable<std::vec::IntoIter<Extent>>,
+
+    // Open nodes with the byte offset each one ends at; the root never closes
+    stack: Vec<(SyntaxNode<'a>, usize)>,
+
+    // Location of the next unread byte
+    position: usize,
+    line: usize,
+    column: usize,
+    token_end: usize,
+}
+
+impl<'a> Builder<'a> {
+    fn new(source: &'a str, extents: Vec<Extent>) -> Self {
+        let root = SyntaxNode { kind: NodeKind::Root, span: Span::new(0, source.len(), 1, 1), children: Vec::new() };
+
+        Builder {
+            source,
+            extents: extents.into_iter().peekable(),
+            stack: vec![(root, usize::MAX)],
+            position: 0,
+            line: 1,
+            column: 1,
+            token_end: 0,
+        }
+    }
+
+    // Consumes `source[position..end]`, returning its text and span
+    fn take(&mut self, end: usize) -> (&'a str, Span) {
+        let text = &self.source[self.position..end];
+        let span = Span::new(self.position, end, self.line, self.column);
+
+        for ch in text.chars() {
+            if ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+            else {
+                self.column += 1;
+            }
+        }
+        self.position = end;
+
+        (text, span)
+    }
+
+    // Splits the text between tokens into runs of whitespace and of characters the lexer skipped
+    fn gap(&mut self, end: usize) {
+        while self.position < end {
+            let rest = &self.source[self.position..end];
+            let whitespace = rest.starts_with(|ch: char| ch.is_ascii_whitespace());
+            let length = rest.find(|ch: char| ch.is_ascii_whitespace() != whitespace).unwrap_or(rest.len());
+
+            let kind = if whitespace { TriviaKind::Whitespace } else { TriviaKind::Skipped };
+            self.trivia(kind, self.position + length);
+        }
+    }
+
+    fn trivia(&mut self, kind: TriviaKind, end: usize) {
+        self.close_before(self.position);
+        let (text, span) = self.take(end);
+        self.push(SyntaxElement::Trivia(Trivia { kind, text, span }));
+    }
+
+    fn token(&mut self, token: Token<'a>, end: usize) {
+        let start = self.position;
+        self.close_before(start);
+
+        while let Some(extent) = self.extents.next_if(|extent| extent.span.start <= start) {
+            // Nodes that started inside an earlier token, like those in a `${...}`, are dropped
+            let parent_end = self.stack.last().map_or(usize::MAX, |(_, end)| *end);
+            if extent.span.start >= self.token_end && extent.span.end > start {
+                let end = extent.span.end.min(parent_end);
+                let span = Span::new(extent.span.start, end, extent.span.line, extent.span.column);
+                self.stack.push((SyntaxNode { kind: extent.kind, span, children: Vec::new() }, end));
+            }
+        }
+
+        let (text, span) = self.take(end);
+        self.token_end = end;
+        self.push(SyntaxElement::Token(SyntaxToken { token, text, span }));
+    }
+
+    fn push(&mut self, element: SyntaxElement<'a>) {
+        if let Some((node, _)) = self.stack.last_mut() {
+            node.children.push(element);
+        }
+    }
+
+    fn close_before(&mut self, offset: usize) {
+        while self.stack.len() > 1 && self.stack.last().is_some_and(|(_, end)| *end <= offset) {
+            self.close();
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some((node, _)) = self.stack.pop() {
+            self.push(SyntaxElement::Node(node));
+        }
+    }
+
+    fn finish(mut self) -> SyntaxNode<'a> {
+        while self.stack.len() > 1 {
+            self.close();
+        }
+
+        self.stack.pop().map(|(root, _)| root).unwrap_or(SyntaxNode { kind: NodeKind::Root, span: Span::default(), children: Vec::new() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trips(source: &str) {
+        let (tree, _) = SyntaxTree::parse(source);
+        assert_eq!(tree.to_string(), source, "printing the tree of {:?}", source);
+    }
+
+    // The first node of `kind` under `node`, searching depth first
+    fn find<'n, 'a>(node: &'n SyntaxNode<'a>, kind: NodeKind) -> Option<&'n SyntaxNode<'a>> {
+        node.children.iter().find_map(|child| match child {
+            SyntaxElement::Node(child) if child.kind == kind => Some(child),
+            SyntaxElement::Node(child) => find(child, kind),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        assert_round_trips("// setup\nlet int a = 1; // trailing\n");
+        assert_round_trips("/* block\n   comment */ print(a)\n");
+    }
+
+    #[test]
+    fn line_endings_are_kept() {
+        assert_round_trips("let int a = 1;\r\nprint(a)\r\n");
+        assert_round_trips("print(\"no newline at the end\")");
+    }
+
+    #[test]
+    fn strings_are_kept() {
+        assert_round_trips("let str name = \"world\";\nprint(\"Hello ${name}!\")\n");
+        assert_round_trips("print(\"\"\"\n    raw ${text}\n    \"\"\")\n");
+        assert_round_trips("print(\"tab\\tquote\\\"\")\n");
+    }
+
+    #[test]
+    fn double_minus_is_kept() {
+        assert_round_trips("let int x = --y;\nx--;\nprint(x--1)\n");
+
+        let (tree, errors) = SyntaxTree::parse("let int x = --y;");
+        assert!(errors.is_empty());
+        let negation = find(tree.root(), NodeKind::Unary).expect("a unary node");
+        assert_eq!(negation.to_string(), "--y");
+    }
+
+    #[test]
+    fn source_with_errors_is_kept() {
+        assert_round_trips("print(\"unterminated\n");
+        assert_round_trips("let int a = 1;\n/* never closed\nprint(a)\n");
+        assert_round_trips("let int a = 1 $ 2;\nprint(a @ #)\n");
+    }
+
+    #[test]
+    fn rejected_characters_are_skipped_trivia() {
+        let (tree, errors) = SyntaxTree::parse("print(1) $");
+        assert!(!errors.is_empty());
+        assert!(tree.root().children.iter().any(|child| matches!(child,
+            SyntaxElement::Trivia(Trivia { kind: TriviaKind::Skipped, text: "$", .. }))));
+    }
+
+    #[test]
+    fn let_holds_its_value() {
+        let source = "let int total = a + 1;";
+        let (tree, errors) = SyntaxTree::parse(source);
+        assert!(errors.is_empty());
+
+        let statement = find(tree.root(), NodeKind::Let).expect("a let node");
+        assert_eq!((statement.span.start, statement.span.end), (0, source.len()));
+
+        let value = find(statement, NodeKind::Binary).expect("a binary node inside the let");
+        assert_eq!(value.to_string(), "a + 1");
+        let texts: Vec<&str> = value.tokens().iter().map(|token| token.text).collect();
+        assert_eq!(texts, ["a", "+", "1"]);
+    }
+
+    #[test]
+    fn statements_around_an_error_keep_their_nodes() {
+        let source = "let int a = 1;\nlet int b = ;\nprint(a)\n";
+        let (tree, errors) = SyntaxTree::parse(source);
+        assert!(!errors.is_empty());
+        assert_eq!(tree.to_string(), source);
+
+        let kinds: Vec<NodeKind> = tree.root().children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node.kind),
+            _ => None,
+        }).collect();
+        assert_eq!(kinds, [NodeKind::Let, NodeKind::Error, NodeKind::ExpressionStmt]);
+
+        let error = find(tree.root(), NodeKind::Error).expect("an error node");
+        assert_eq!(error.to_string(), "let int b = ;");
+    }
+
+    #[test]
+    fn errors_inside_a_block_stay_inside_it() {
+        let source = "if int (a == 1) {\n    let int b = ;\n    print(a)\n}\n";
+        let (tree, errors) = SyntaxTree::parse(source);
+        assert!(!errors.is_empty());
+        assert_eq!(tree.to_string(), source);
+
+        let statement = find(tree.root(), NodeKind::If).expect("an if node");
+        assert!(find(statement, NodeKind::Error).is_some());
+        assert!(find(statement, NodeKind::ExpressionStmt).is_some());
+    }
+}